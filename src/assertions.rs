@@ -0,0 +1,126 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+/// A check on the response body. A service with assertions is only UP when
+/// the status code is acceptable and every assertion passes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Assertion {
+    Contains { value: String },
+    NotContains { value: String },
+    Regex { pattern: String },
+    /// Without `equals` the path only has to match something.
+    JsonPath {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        equals: Option<Value>,
+    },
+}
+
+impl Assertion {
+    /// Rejects patterns and paths that can never be evaluated.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Assertion::Regex { pattern } => Regex::new(pattern)
+                .map(|_| ())
+                .map_err(|e| format!("Invalid regex {}: {}", pattern, e)),
+            Assertion::JsonPath { path, .. } => JsonPath::parse(path)
+                .map(|_| ())
+                .map_err(|e| format!("Invalid JSONPath {}: {}", path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check(&self, body: &str) -> Result<(), String> {
+        match self {
+            Assertion::Contains { value } => {
+                if body.contains(value.as_str()) { Ok(()) } else { Err(format!("body does not contain {:?}", value)) }
+            }
+            Assertion::NotContains { value } => {
+                if body.contains(value.as_str()) { Err(format!("body contains {:?}", value)) } else { Ok(()) }
+            }
+            Assertion::Regex { pattern } => {
+                let re = Regex::new(pattern).map_err(|e| e.to_string())?;
+                if re.is_match(body) { Ok(()) } else { Err(format!("body does not match /{}/", pattern)) }
+            }
+            Assertion::JsonPath { path, equals } => {
+                let json: Value = serde_json::from_str(body).map_err(|e| format!("body is not JSON: {}", e))?;
+                let query = JsonPath::parse(path).map_err(|e| e.to_string())?;
+                let nodes = query.query(&json).all();
+                match (nodes.first(), equals) {
+                    (None, _) => Err(format!("{} matched nothing", path)),
+                    (Some(_), None) => Ok(()),
+                    (Some(actual), Some(expected)) if *actual == expected => Ok(()),
+                    (Some(actual), Some(expected)) => Err(format!("{} is {}, expected {}", path, actual, expected)),
+                }
+            }
+        }
+    }
+}
+
+/// Evaluates assertions in order, returning the first failure.
+pub fn evaluate(assertions: &[Assertion], body: &str) -> Result<(), String> {
+    for assertion in assertions {
+        assertion.check(body).map_err(|e| format!("Assertion failed: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assertions(json: Value) -> Vec<Assertion> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    const BODY: &str = r#"{"status": "ok", "checks": [{"name": "db", "healthy": true}], "version": "1.4.2"}"#;
+
+    #[test]
+    fn passes_when_every_assertion_holds() {
+        let all = assertions(serde_json::json!([
+            {"type": "contains", "value": "\"status\": \"ok\""},
+            {"type": "not_contains", "value": "error"},
+            {"type": "regex", "pattern": r#""version": "1\.\d+\.\d+""#},
+            {"type": "json_path", "path": "$.checks[?@.name == 'db'].healthy", "equals": true},
+            {"type": "json_path", "path": "$.version"},
+        ]));
+        assert_eq!(evaluate(&all, BODY), Ok(()));
+        assert_eq!(evaluate(&[], ""), Ok(()));
+    }
+
+    #[test]
+    fn reports_the_first_failure() {
+        let failing = assertions(serde_json::json!([
+            {"type": "contains", "value": "ok"},
+            {"type": "json_path", "path": "$.status", "equals": "degraded"},
+            {"type": "contains", "value": "missing"},
+        ]));
+        assert_eq!(evaluate(&failing, BODY), Err("Assertion failed: $.status is \"ok\", expected \"degraded\"".to_string()));
+
+        for (assertion, error) in [
+            (serde_json::json!({"type": "contains", "value": "missing"}), "body does not contain \"missing\""),
+            (serde_json::json!({"type": "not_contains", "value": "db"}), "body contains \"db\""),
+            (serde_json::json!({"type": "regex", "pattern": "^ok$"}), "body does not match /^ok$/"),
+            (serde_json::json!({"type": "json_path", "path": "$.uptime"}), "$.uptime matched nothing"),
+        ] {
+            let assertion: Assertion = serde_json::from_value(assertion).unwrap();
+            assert_eq!(evaluate(&[assertion], BODY), Err(format!("Assertion failed: {}", error)));
+        }
+    }
+
+    #[test]
+    fn json_path_needs_a_json_body() {
+        let assertion = assertions(serde_json::json!([{"type": "json_path", "path": "$.status"}]));
+        assert!(evaluate(&assertion, "<html>ok</html>").unwrap_err().starts_with("Assertion failed: body is not JSON"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns_and_paths() {
+        assert!(assertions(serde_json::json!([{"type": "regex", "pattern": "("}]))[0].validate().is_err());
+        assert!(assertions(serde_json::json!([{"type": "json_path", "path": "status"}]))[0].validate().is_err());
+        assert!(assertions(serde_json::json!([{"type": "json_path", "path": "$.status"}]))[0].validate().is_ok());
+    }
+}
@@ -47,12 +47,9 @@ pub fn extract_session_id(cookie: &str) -> Option<String> {
     cookie
         .split(';')
         .find_map(|pair| {
-            let pair = pair.trim();
-            if pair.starts_with("session_id=") {
-                Some(pair[11..].to_string())
-            } else {
-                None
-            }
+            pair.trim()
+                .strip_prefix("session_id=")
+                .map(|id| id.to_string())
         })
 }
 
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Stdio;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::process::Command;
+use crate::config::CONFIG;
+
+/// Environment variables a service may set. Anything else could change which
+/// program runs (`PATH`) or load code into it (`LD_PRELOAD`, `BASH_ENV`,
+/// `PYTHONPATH`, `NODE_OPTIONS`, ...).
+pub const ALLOWED_ENV: [&str; 6] = ["LANG", "LANGUAGE", "LC_ALL", "LC_MESSAGES", "TZ", "NO_COLOR"];
+
+/// `check_config` of a `command` service. The program itself is the
+/// service's `healthcheck_url`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandConfig {
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Added to the collector's own environment, names from [`ALLOWED_ENV`] only.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub expected_exit_code: i32,
+    /// Regular expression stdout must match.
+    pub stdout_pattern: Option<String>,
+}
+
+impl CommandConfig {
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        let config: Self = serde_json::from_value(config.clone()).map_err(|e| format!("Invalid command check config: {}", e))?;
+        if let Some(name) = config.env.keys().find(|name| !ALLOWED_ENV.contains(&name.as_str())) {
+            return Err(format!("Setting {} is not allowed, command checks may only set {}", name, ALLOWED_ENV.join(", ")));
+        }
+        if config.working_dir.as_deref().is_some_and(|dir| !Path::new(dir).is_absolute()) {
+            return Err("working_dir must be an absolute path".to_string());
+        }
+        if let Some(pattern) = &config.stdout_pattern {
+            Regex::new(pattern).map_err(|e| format!("Invalid stdout_pattern {}: {}", pattern, e))?;
+        }
+        Ok(config)
+    }
+}
+
+/// Command checks run programs on the host and the service API is open to
+/// anyone who can reach it, so only programs listed in
+/// `COMMAND_CHECKS_ALLOWED` may be run. Paths must be absolute so `PATH`
+/// plays no part in which program that is.
+pub fn validate(program: &str, config: &Value) -> Result<(), String> {
+    if program.trim().is_empty() {
+        return Err("Command check needs a program to run".to_string());
+    }
+    if !Path::new(program).is_absolute() {
+        return Err(format!("Command check program {} must be an absolute path", program));
+    }
+    if CONFIG.command_checks_allowed.is_empty() {
+        return Err("Command checks are disabled, list the programs they may run in COMMAND_CHECKS_ALLOWED".to_string());
+    }
+    if !CONFIG.command_checks_allowed.iter().any(|allowed| allowed == program) {
+        return Err(format!("{} is not listed in COMMAND_CHECKS_ALLOWED", program));
+    }
+    CommandConfig::parse(config).map(|_| ())
+}
+
+/// Runs the program and compares its exit code and stdout with the config.
+/// The process is killed when the check times out.
+pub async fn probe(program: &str, config: &Value) -> Result<(), String> {
+    validate(program, config)?;
+    run(program, &CommandConfig::parse(config)?).await
+}
+
+async fn run(program: &str, config: &CommandConfig) -> Result<(), String> {
+
+    let mut command = Command::new(program);
+    command
+        .args(&config.args)
+        .envs(&config.env)
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(dir) = &config.working_dir {
+        command.current_dir(dir);
+    }
+
+    let output = command.output().await.map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    match output.status.code() {
+        Some(code) if code == config.expected_exit_code => {}
+        Some(code) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let detail = last_line(&stderr).or_else(|| last_line(&stdout));
+            return Err(match detail {
+                Some(line) => format!("Exited with code {}, expected {}: {}", code, config.expected_exit_code, line),
+                None => format!("Exited with code {}, expected {}", code, config.expected_exit_code),
+            });
+        }
+        None => return Err("Terminated by a signal".to_string()),
+    }
+
+    if let Some(pattern) = &config.stdout_pattern {
+        let re = Regex::new(pattern).map_err(|e| e.to_string())?;
+        if !re.is_match(&stdout) {
+            return Err(format!("stdout does not match /{}/", pattern));
+        }
+    }
+    Ok(())
+}
+
+fn last_line(output: &str) -> Option<&str> {
+    output.lines().map(str::trim).rfind(|line| !line.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn shell(script: &str, config: Value) -> CommandConfig {
+        let mut config = CommandConfig::parse(&config).unwrap();
+        config.args = vec!["-c".to_string(), script.to_string()];
+        config
+    }
+
+    #[test]
+    fn only_allows_locale_variables() {
+        assert!(CommandConfig::parse(&json!({"env": {"LC_ALL": "C", "TZ": "UTC"}})).is_ok());
+        for name in ["PATH", "LD_PRELOAD", "BASH_ENV", "PYTHONPATH", "PERL5OPT", "NODE_OPTIONS"] {
+            assert!(CommandConfig::parse(&json!({"env": {name: "x"}})).is_err(), "{} should be rejected", name);
+        }
+    }
+
+    #[test]
+    fn requires_absolute_paths() {
+        assert!(validate("df", &json!({})).unwrap_err().contains("absolute"));
+        assert!(CommandConfig::parse(&json!({"working_dir": "checks"})).is_err());
+    }
+
+    #[tokio::test]
+    async fn checks_exit_code_and_stdout() {
+        assert_eq!(run("/bin/sh", &shell("echo OK", json!({"stdout_pattern": "^OK"}))).await, Ok(()));
+        assert_eq!(run("/bin/sh", &shell("echo $TZ", json!({"env": {"TZ": "UTC"}, "stdout_pattern": "UTC"}))).await, Ok(()));
+        assert_eq!(
+            run("/bin/sh", &shell("echo full >&2; exit 2", json!({}))).await.unwrap_err(),
+            "Exited with code 2, expected 0: full"
+        );
+        assert_eq!(run("/bin/sh", &shell("echo degraded", json!({"stdout_pattern": "^OK"}))).await.unwrap_err(), "stdout does not match /^OK/");
+    }
+}
@@ -0,0 +1,227 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use chrono::{DateTime, Utc};
+use hyper::header::HeaderValue;
+use hyper::Uri;
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use hyper_util::client::proxy::matcher::{Intercept, Matcher};
+use hyper_util::rt::TokioIo;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_native_tls::TlsStream;
+use super::{elapsed_ms, tcp, tls, PhaseTimings};
+
+/// `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`, read once.
+static PROXIES: Lazy<Matcher> = Lazy::new(Matcher::from_env);
+
+tokio::task_local! {
+    static TIMINGS: Arc<Mutex<PhaseTimings>>;
+}
+
+/// Expiry of the server certificate, attached to every response received on
+/// a TLS connection.
+#[derive(Debug, Clone, Copy)]
+pub struct CertificateExpiry(pub DateTime<Utc>);
+
+/// Runs `future` and returns the phases of the last connection it opened,
+/// including those completed before a failure. They are left empty when it
+/// only used pooled connections.
+pub async fn timed<F: Future>(future: F) -> (F::Output, PhaseTimings) {
+    let timings = Arc::new(Mutex::new(PhaseTimings::default()));
+    let output = TIMINGS.scope(timings.clone(), future).await;
+    let timings = timings.lock().clone();
+    (output, timings)
+}
+
+fn record(update: impl FnOnce(&mut PhaseTimings)) {
+    let _ = TIMINGS.try_with(|timings| update(&mut timings.lock()));
+}
+
+/// `Proxy-Authorization` to send with plain HTTP requests going through a
+/// proxy. HTTPS requests authenticate when the tunnel is opened instead.
+pub fn proxy_authorization(uri: &Uri) -> Option<HeaderValue> {
+    match uri.scheme_str() {
+        Some("http") => PROXIES.intercept(uri)?.basic_auth().cloned(),
+        _ => None,
+    }
+}
+
+/// Opens connections for the HTTP check client, recording their phases for
+/// [`timed`]. Connections go through the proxy from the environment, as
+/// reqwest's default client did.
+///
+/// TLS uses native-tls, which reqwest's default client is built on and so is
+/// linked in regardless, to keep verifying `http` checks exactly as they
+/// were before the client was replaced.
+#[derive(Clone)]
+pub struct Connector {
+    tls: tokio_native_tls::TlsConnector,
+}
+
+impl Connector {
+    pub fn new(tls: native_tls::TlsConnector) -> Self {
+        Self { tls: tls.into() }
+    }
+}
+
+impl Default for Connector {
+    fn default() -> Self {
+        Self::new(native_tls::TlsConnector::new().expect("Failed to build TLS connector"))
+    }
+}
+
+impl tower::Service<Uri> for Connector {
+    type Response = TokioIo<Stream>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<TokioIo<Stream>>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let tls = self.tls.clone();
+        Box::pin(async move { connect(&uri, &tls).await.map(TokioIo::new) })
+    }
+}
+
+async fn connect(uri: &Uri, tls: &tokio_native_tls::TlsConnector) -> io::Result<Stream> {
+    let https = uri.scheme_str() == Some("https");
+    let host = uri
+        .host()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no host", uri)))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+    let proxy = PROXIES.intercept(uri);
+
+    // Through a proxy, DNS and connect are those of the proxy
+    let mut timings = PhaseTimings::default();
+    let result = match &proxy {
+        Some(proxy) => {
+            let (proxy_host, proxy_port) = proxy_address(proxy)?;
+            tcp::connect(&proxy_host, proxy_port, &mut timings).await
+        }
+        None => tcp::connect(host, port, &mut timings).await,
+    };
+    record(|recorded| *recorded = timings);
+    let mut stream = result?;
+
+    if !https {
+        return Ok(Stream { io: Io::Tcp(stream), proxied: proxy.is_some(), certificate_expiry: None });
+    }
+    if let Some(proxy) = &proxy {
+        tunnel(&mut stream, host, port, proxy).await?;
+    }
+
+    let start = Instant::now();
+    let stream = tls.connect(host, stream).await.map_err(io::Error::other)?;
+    record(|recorded| recorded.tls_ms = Some(elapsed_ms(start)));
+    let certificate_expiry = stream
+        .get_ref()
+        .peer_certificate()
+        .ok()
+        .flatten()
+        .and_then(|cert| cert.to_der().ok())
+        .and_then(|der| tls::certificate_expiry(&der).ok());
+    Ok(Stream { io: Io::Tls(Box::new(stream)), proxied: false, certificate_expiry })
+}
+
+fn proxy_address(proxy: &Intercept) -> io::Result<(String, u16)> {
+    let uri = proxy.uri();
+    if uri.scheme_str() != Some("http") {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!("Unsupported proxy {}, only http:// proxies are supported", uri)));
+    }
+    let host = uri.host().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    Ok((host.to_string(), uri.port_u16().unwrap_or(80)))
+}
+
+/// Asks the proxy to open a tunnel to `host:port`.
+async fn tunnel(stream: &mut TcpStream, host: &str, port: u16, proxy: &Intercept) -> io::Result<()> {
+    let authority = if host.contains(':') { format!("[{}]:{}", host, port) } else { format!("{}:{}", host, port) };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some(authorization) = proxy.basic_auth().and_then(|value| value.to_str().ok()) {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // The proxy sends nothing after its response until the TLS handshake
+    // starts, so this cannot read past the headers
+    let mut response = Vec::new();
+    let mut buf = [0; 1024];
+    while !response.windows(4).any(|window| window == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || response.len() > 8192 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Proxy sent no valid response to CONNECT {}", authority)));
+        }
+        response.extend_from_slice(&buf[..n]);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!("Proxy refused to tunnel to {}: {}", authority, status_line))),
+    }
+}
+
+pub struct Stream {
+    io: Io,
+    /// Plain HTTP through a proxy, which needs absolute request URIs.
+    proxied: bool,
+    certificate_expiry: Option<DateTime<Utc>>,
+}
+
+enum Io {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        let connected = Connected::new().proxy(self.proxied);
+        match self.certificate_expiry {
+            Some(expiry) => connected.extra(CertificateExpiry(expiry)),
+            None => connected,
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            Io::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Io::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().io {
+            Io::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Io::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            Io::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Io::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            Io::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Io::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
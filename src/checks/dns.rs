@@ -0,0 +1,225 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::proto::rr::{RData, RecordType};
+use hickory_resolver::TokioAsyncResolver;
+use serde::Deserialize;
+use serde_json::Value;
+use super::Attempt;
+
+const RECORD_TYPES: [&str; 6] = ["A", "AAAA", "CNAME", "MX", "TXT", "SRV"];
+
+/// `check_config` of a `dns` service.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DnsConfig {
+    #[serde(default = "default_record_type")]
+    pub record_type: String,
+    /// `ip` or `ip:port`; the system resolver is used when unset.
+    pub resolver: Option<String>,
+    #[serde(default)]
+    pub expected: Vec<String>,
+    #[serde(default, rename = "match")]
+    pub match_mode: MatchMode,
+}
+
+/// How the answer set is compared with `expected`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The answers are exactly the expected records.
+    #[default]
+    Exact,
+    /// Every expected record is among the answers.
+    Contains,
+}
+
+fn default_record_type() -> String {
+    "A".to_string()
+}
+
+impl DnsConfig {
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        let config: DnsConfig = serde_json::from_value(config.clone()).map_err(|e| format!("Invalid DNS check config: {}", e))?;
+        if !RECORD_TYPES.contains(&config.record_type.to_uppercase().as_str()) {
+            return Err(format!("Unsupported record type {}. Expected one of: {}", config.record_type, RECORD_TYPES.join(", ")));
+        }
+        if let Some(resolver) = &config.resolver {
+            parse_resolver(resolver)?;
+        }
+        Ok(config)
+    }
+
+    fn record_type(&self) -> RecordType {
+        self.record_type.to_uppercase().parse().unwrap_or(RecordType::A)
+    }
+}
+
+fn parse_resolver(resolver: &str) -> Result<SocketAddr, String> {
+    resolver
+        .parse::<SocketAddr>()
+        .or_else(|_| resolver.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+        .map_err(|_| format!("Invalid resolver {}, expected an IP address with an optional port", resolver))
+}
+
+/// Canonical text form of a record, compared case-insensitively and without
+/// trailing dots. MX is `preference exchange`, SRV `priority weight port target`.
+fn record_text(rdata: &RData) -> Option<String> {
+    let text = match rdata {
+        RData::A(a) => a.to_string(),
+        RData::AAAA(aaaa) => aaaa.to_string(),
+        RData::CNAME(name) => name.to_string(),
+        RData::MX(mx) => format!("{} {}", mx.preference(), mx.exchange()),
+        RData::TXT(txt) => txt.iter().map(|part| String::from_utf8_lossy(part).into_owned()).collect(),
+        RData::SRV(srv) => format!("{} {} {} {}", srv.priority(), srv.weight(), srv.port(), srv.target()),
+        _ => return None,
+    };
+    Some(normalize(&text))
+}
+
+fn normalize(record: &str) -> String {
+    record.trim().trim_end_matches('.').to_lowercase()
+}
+
+/// Resolves the name and compares the answers with the expected records.
+pub async fn check(name: &str, config: &Value, timeout: Duration) -> Attempt {
+    let config = match DnsConfig::parse(config) {
+        Ok(config) => config,
+        Err(e) => return Attempt::down(0.0, e),
+    };
+
+    let resolver_config = match &config.resolver {
+        Some(resolver) => match parse_resolver(resolver) {
+            Ok(addr) => ResolverConfig::from_parts(None, vec![], NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true)),
+            Err(e) => return Attempt::down(0.0, e),
+        },
+        None => match hickory_resolver::system_conf::read_system_conf() {
+            Ok((resolver_config, _)) => resolver_config,
+            Err(e) => return Attempt::down(0.0, format!("Failed to read system resolver configuration: {}", e)),
+        },
+    };
+    // Every check must reach the server, so nothing is cached between lookups
+    let mut opts = ResolverOpts::default();
+    opts.cache_size = 0;
+    opts.timeout = timeout;
+    opts.attempts = 1;
+    let resolver = TokioAsyncResolver::tokio(resolver_config, opts);
+
+    let record_type = config.record_type();
+    let start = Instant::now();
+    let lookup = tokio::time::timeout(timeout, resolver.lookup(name, record_type)).await;
+    let latency_seconds = start.elapsed().as_secs_f64();
+
+    let lookup = match lookup {
+        Ok(Ok(lookup)) => lookup,
+        Ok(Err(e)) => return Attempt::down(latency_seconds, format!("{} lookup for {} failed: {}", record_type, name, e)),
+        Err(_) => return Attempt::down(latency_seconds, format!("{} lookup for {} timed out", record_type, name)),
+    };
+
+    let mut answers: Vec<String> = lookup
+        .record_iter()
+        .filter(|record| record.record_type() == record_type)
+        .filter_map(|record| record.data().and_then(record_text))
+        .collect();
+    answers.sort();
+    answers.dedup();
+    let mut expected: Vec<String> = config.expected.iter().map(|record| normalize(record)).collect();
+    expected.sort();
+    expected.dedup();
+
+    let matches = match config.match_mode {
+        _ if expected.is_empty() => !answers.is_empty(),
+        MatchMode::Exact => answers == expected,
+        MatchMode::Contains => expected.iter().all(|record| answers.contains(record)),
+    };
+    if matches {
+        Attempt::up(latency_seconds)
+    } else {
+        Attempt::down(
+            latency_seconds,
+            format!("{} records for {} were [{}], expected [{}]", record_type, name, answers.join(", "), expected.join(", ")),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use hickory_resolver::proto::op::{Message, MessageType};
+    use hickory_resolver::proto::rr::rdata::{A, MX};
+    use hickory_resolver::proto::rr::{Name, Record};
+    use tokio::net::UdpSocket;
+    use super::*;
+
+    /// Answers A queries with two addresses and MX queries with two
+    /// exchanges, in mixed case and order.
+    async fn fake_resolver() -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let resolver = socket.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+            loop {
+                let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let request = Message::from_vec(&buf[..n]).unwrap();
+                let query = request.queries()[0].clone();
+                let answers = match query.query_type() {
+                    RecordType::A => vec![RData::A(A::new(10, 0, 0, 2)), RData::A(A::new(10, 0, 0, 1))],
+                    RecordType::MX => vec![
+                        RData::MX(MX::new(20, Name::from_ascii("Backup.Example.com.").unwrap())),
+                        RData::MX(MX::new(10, Name::from_ascii("mail.example.com.").unwrap())),
+                    ],
+                    _ => Vec::new(),
+                };
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(true)
+                    .set_recursion_available(true)
+                    .add_answers(answers.into_iter().map(|rdata| Record::from_rdata(query.name().clone(), 60, rdata)))
+                    .add_query(query);
+                socket.send_to(&response.to_vec().unwrap(), peer).await.unwrap();
+            }
+        });
+        resolver
+    }
+
+    async fn lookup(name: &str, config: Value) -> Attempt {
+        check(name, &config, Duration::from_secs(5)).await
+    }
+
+    #[tokio::test]
+    async fn compares_the_answer_set() {
+        let resolver = fake_resolver().await;
+        let attempt = lookup("api.example.com", serde_json::json!({"resolver": resolver, "expected": ["10.0.0.1", "10.0.0.2"]})).await;
+        assert_eq!(attempt.status, "UP", "{:?}", attempt.error);
+
+        let attempt = lookup("api.example.com", serde_json::json!({"resolver": resolver, "expected": ["10.0.0.1"]})).await;
+        assert_eq!(attempt.error.as_deref(), Some("A records for api.example.com were [10.0.0.1, 10.0.0.2], expected [10.0.0.1]"));
+
+        let attempt = lookup("api.example.com", serde_json::json!({"resolver": resolver, "expected": ["10.0.0.1"], "match": "contains"})).await;
+        assert_eq!(attempt.status, "UP", "{:?}", attempt.error);
+    }
+
+    #[tokio::test]
+    async fn normalizes_records() {
+        let resolver = fake_resolver().await;
+        let config = serde_json::json!({"resolver": resolver, "record_type": "mx", "expected": ["10 MAIL.example.com", "20 backup.example.com."]});
+        let attempt = lookup("example.com", config).await;
+        assert_eq!(attempt.status, "UP", "{:?}", attempt.error);
+    }
+
+    #[tokio::test]
+    async fn any_answer_passes_without_expected_records() {
+        let resolver = fake_resolver().await;
+        assert_eq!(lookup("api.example.com", serde_json::json!({"resolver": resolver})).await.status, "UP");
+        assert_eq!(lookup("api.example.com", serde_json::json!({"resolver": resolver, "record_type": "TXT"})).await.status, "DOWN");
+    }
+
+    #[test]
+    fn rejects_unsupported_config() {
+        assert!(DnsConfig::parse(&serde_json::json!({"record_type": "PTR"})).is_err());
+        assert!(DnsConfig::parse(&serde_json::json!({"resolver": "dns.example.com"})).is_err());
+        assert_eq!(parse_resolver("10.0.0.53").unwrap(), SocketAddr::from((Ipv4Addr::new(10, 0, 0, 53), 53)));
+    }
+}
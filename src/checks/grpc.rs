@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use serde_json::Value;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+use super::Attempt;
+
+/// `check_config` of a `grpc` service.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrpcConfig {
+    /// Service name sent in the health request; empty asks about the whole server.
+    #[serde(default)]
+    pub service: String,
+}
+
+impl GrpcConfig {
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        serde_json::from_value(config.clone()).map_err(|e| format!("Invalid gRPC check config: {}", e))
+    }
+}
+
+/// Builds the endpoint for `http://host:port` (plaintext), `https://host:port`
+/// (TLS) or a bare `host:port`, which is treated as plaintext.
+pub fn endpoint(target: &str) -> Result<Endpoint, String> {
+    let uri = if target.contains("://") { target.to_string() } else { format!("http://{}", target) };
+    let endpoint = Endpoint::from_shared(uri).map_err(|e| format!("Invalid gRPC target {}: {}", target, e))?;
+    if target.starts_with("https://") {
+        endpoint
+            .tls_config(ClientTlsConfig::new().with_webpki_roots())
+            .map_err(|e| format!("Invalid gRPC TLS configuration: {}", e))
+    } else if target.starts_with("http://") || !target.contains("://") {
+        Ok(endpoint)
+    } else {
+        Err(format!("Invalid gRPC target {}, expected http://, https:// or host:port", target))
+    }
+}
+
+/// Calls `grpc.health.v1.Health/Check`; only SERVING counts as up.
+pub async fn check(target: &str, config: &Value, timeout: Duration) -> Attempt {
+    let start = Instant::now();
+    let config = match GrpcConfig::parse(config) {
+        Ok(config) => config,
+        Err(e) => return Attempt::down(0.0, e),
+    };
+    let endpoint = match endpoint(target) {
+        Ok(endpoint) => endpoint,
+        Err(e) => return Attempt::down(0.0, e),
+    };
+
+    let channel = match endpoint.connect_timeout(timeout).timeout(timeout).connect().await {
+        Ok(channel) => channel,
+        Err(e) => {
+            // The transport error itself only says "transport error", the cause is in its sources
+            let mut causes = Vec::new();
+            let mut source = std::error::Error::source(&e);
+            while let Some(cause) = source {
+                causes.push(cause.to_string());
+                source = cause.source();
+            }
+            causes.dedup();
+            let cause = if causes.is_empty() { e.to_string() } else { causes.join(": ") };
+            return Attempt::down(start.elapsed().as_secs_f64(), format!("gRPC connection to {} failed: {}", target, cause));
+        }
+    };
+    let response = HealthClient::new(channel)
+        .check(HealthCheckRequest { service: config.service.clone() })
+        .await;
+    let latency_seconds = start.elapsed().as_secs_f64();
+
+    match response.map(|response| response.into_inner().status()) {
+        Ok(ServingStatus::Serving) => Attempt::up(latency_seconds),
+        Ok(status) => Attempt::down(latency_seconds, format!("gRPC health status is {}", status.as_str_name())),
+        Err(status) => Attempt::down(latency_seconds, format!("gRPC health check failed: {:?}: {}", status.code(), status.message())),
+    }
+}
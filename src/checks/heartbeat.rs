@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::Value;
+use crate::models::Service;
+use super::Attempt;
+
+/// `check_config` of a `heartbeat` service.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    /// Extra time allowed after `healthcheck_duration_seconds` before the
+    /// heartbeat counts as missed.
+    #[serde(default = "default_grace_seconds")]
+    pub grace_seconds: i64,
+}
+
+fn default_grace_seconds() -> i64 {
+    60
+}
+
+impl HeartbeatConfig {
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        let config: HeartbeatConfig = serde_json::from_value(config.clone()).map_err(|e| format!("Invalid heartbeat check config: {}", e))?;
+        if config.grace_seconds < 0 {
+            return Err("grace_seconds must not be negative".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// Heartbeats received since the collector loaded its services, which only
+/// know the last heartbeat as of that moment.
+static LAST_HEARTBEATS: Lazy<Mutex<HashMap<i64, DateTime<Utc>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn new_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+pub fn record(service_id: i64, at: DateTime<Utc>) {
+    LAST_HEARTBEATS.lock().insert(service_id, at);
+}
+
+/// Drops the heartbeat of a deleted service.
+pub fn forget(service_id: i64) {
+    LAST_HEARTBEATS.lock().remove(&service_id);
+}
+
+fn last_heartbeat(service: &Service) -> Option<DateTime<Utc>> {
+    LAST_HEARTBEATS.lock().get(&service.id).copied().or(service.last_heartbeat_at)
+}
+
+/// When the next heartbeat is due at the latest: the expected interval plus
+/// grace after the last heartbeat, or after the creation of the service
+/// before the first one.
+pub fn deadline(service: &Service) -> DateTime<Utc> {
+    let grace_seconds = HeartbeatConfig::parse(&service.check_config).map(|c| c.grace_seconds).unwrap_or_else(|_| default_grace_seconds());
+    let since = last_heartbeat(service).unwrap_or(service.created_at);
+    since + chrono::Duration::seconds(service.healthcheck_duration_seconds.max(0) + grace_seconds)
+}
+
+/// UP until the [`deadline`] has passed.
+pub fn check(service: &Service) -> Attempt {
+    if Utc::now() <= deadline(service) {
+        return Attempt::up(0.0);
+    }
+    match last_heartbeat(service) {
+        Some(last) => Attempt::down(0.0, format!("No heartbeat since {}", last.format("%Y-%m-%d %H:%M:%S UTC"))),
+        None => Attempt::down(0.0, "No heartbeat received yet"),
+    }
+}
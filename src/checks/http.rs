@@ -0,0 +1,424 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use once_cell::sync::Lazy;
+use reqwest::cookie::{CookieStore, Jar};
+use crate::assertions;
+use crate::config::CONFIG;
+use crate::models::Service;
+use super::connector::{self, CertificateExpiry, Connector};
+use super::{elapsed_ms, Attempt, PhaseTimings};
+
+const MAX_REDIRECTS: usize = 10;
+
+type HttpClient = Client<Connector, Full<Bytes>>;
+
+/// Shared by all HTTP checks so connections are pooled between them.
+static CLIENT: Lazy<HttpClient> = Lazy::new(|| client(Connector::default()));
+
+fn client(connector: Connector) -> HttpClient {
+    Client::builder(TokioExecutor::new())
+        .pool_timer(TokioTimer::new())
+        .pool_max_idle_per_host(CONFIG.http_pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(CONFIG.http_pool_idle_timeout_seconds))
+        .build(connector)
+}
+
+/// Sends the configured request. DNS, connect and TLS are timed when the
+/// check opens a connection rather than reusing a pooled one, and the
+/// certificate expiry is read from the connection the response came on.
+pub async fn check(service: &Service, timeout: Duration) -> Attempt {
+    check_with(&CLIENT, service, timeout).await
+}
+
+async fn check_with(client: &HttpClient, service: &Service, timeout: Duration) -> Attempt {
+    let start = Instant::now();
+    let (result, phases) = connector::timed(tokio::time::timeout(timeout, exchange(client, service, start))).await;
+    let mut attempt = result.unwrap_or_else(|_| Attempt::down(start.elapsed().as_secs_f64(), "HTTP request timed out"));
+    let ttfb_ms = attempt.timings.take().and_then(|timings| timings.ttfb_ms);
+    attempt.timings = Some(PhaseTimings { ttfb_ms, ..phases });
+    attempt
+}
+
+/// Checks the status code and body assertions of the final response.
+async fn exchange(client: &HttpClient, service: &Service, start: Instant) -> Attempt {
+    let resp = match send_service(client, service).await {
+        Ok(resp) => resp,
+        Err(e) => return Attempt::down(start.elapsed().as_secs_f64(), e),
+    };
+    let ttfb_ms = elapsed_ms(start);
+    let tls_expires_at = resp.extensions().get::<CertificateExpiry>().map(|expiry| expiry.0);
+    let mut attempt = evaluate_response(service, resp, start).await;
+    attempt.timings = Some(PhaseTimings { ttfb_ms: Some(ttfb_ms), ..Default::default() });
+    attempt.tls_expires_at = tls_expires_at;
+    attempt
+}
+
+/// Sends a request through the shared client, following redirects as an
+/// `http` check does. Cookies are sent from and stored in `cookies`, unless
+/// `headers` already has a `Cookie`.
+pub async fn request(method: &str, url: &str, headers: &BTreeMap<String, String>, body: String, cookies: &Jar) -> Result<Response<Incoming>, String> {
+    let method = Method::from_bytes(method.as_bytes()).unwrap_or(Method::GET);
+    send(&CLIENT, method, url, header_map(headers)?, body, Some(cookies)).await
+}
+
+fn header_map(headers: &BTreeMap<String, String>) -> Result<HeaderMap, String> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name {}", name))?;
+        let value = HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header {}", name))?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+async fn send_service(client: &HttpClient, service: &Service) -> Result<Response<Incoming>, String> {
+    let method = Method::from_bytes(service.http_method.as_bytes()).unwrap_or(Method::GET);
+    let body = service.request_body.clone().unwrap_or_default();
+    send(client, method, &service.healthcheck_url, header_map(&service.request_headers)?, body, None).await
+}
+
+/// Sends the request and follows up to 10 redirects. A 301, 302 or 303
+/// turns it into a body-less GET, and credentials are dropped when the
+/// redirect leaves the host.
+async fn send(
+    client: &HttpClient,
+    mut method: Method,
+    url: &str,
+    mut headers: HeaderMap,
+    mut body: String,
+    cookies: Option<&Jar>,
+) -> Result<Response<Incoming>, String> {
+    let mut url = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    let first_url = url.clone();
+
+    for _ in 0..=MAX_REDIRECTS {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("Unsupported URL scheme in {}", url));
+        }
+        let uri: Uri = url.as_str().parse().map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+        let mut request = Request::new(Full::new(Bytes::from(body.clone())));
+        *request.method_mut() = method.clone();
+        *request.headers_mut() = headers.clone();
+        if let Some(authorization) = connector::proxy_authorization(&uri) {
+            request.headers_mut().insert(header::PROXY_AUTHORIZATION, authorization);
+        }
+        // The jar speaks reqwest's http 0.2 header types
+        if let (Some(jar), false) = (cookies, headers.contains_key(header::COOKIE)) {
+            if let Some(cookie) = jar.cookies(&url).and_then(|cookie| HeaderValue::from_bytes(cookie.as_bytes()).ok()) {
+                request.headers_mut().insert(header::COOKIE, cookie);
+            }
+        }
+        *request.uri_mut() = uri;
+
+        let resp = client.request(request).await.map_err(|e| format!("Request to {} failed: {}", url, describe(&e)))?;
+        if let Some(jar) = cookies {
+            let set_cookies: Vec<_> = resp
+                .headers()
+                .get_all(header::SET_COOKIE)
+                .iter()
+                .filter_map(|value| reqwest::header::HeaderValue::from_bytes(value.as_bytes()).ok())
+                .collect();
+            jar.set_cookies(&mut set_cookies.iter(), &url);
+        }
+        let location = match resp.status() {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER | StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => {
+                resp.headers().get(header::LOCATION).and_then(|location| location.to_str().ok())
+            }
+            _ => None,
+        };
+        let Some(location) = location else {
+            return Ok(resp);
+        };
+        let mut next = url.join(location).map_err(|e| format!("Invalid redirect from {} to {}: {}", url, location, e))?;
+        next.set_fragment(None);
+
+        if matches!(resp.status(), StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER) {
+            if method != Method::HEAD {
+                method = Method::GET;
+            }
+            body.clear();
+            for name in [header::CONTENT_TYPE, header::CONTENT_LENGTH, header::CONTENT_ENCODING, header::TRANSFER_ENCODING] {
+                headers.remove(name);
+            }
+        }
+        if next.host_str() != url.host_str() || next.port_or_known_default() != url.port_or_known_default() {
+            for name in [header::AUTHORIZATION, header::COOKIE, header::PROXY_AUTHORIZATION, header::WWW_AUTHENTICATE] {
+                headers.remove(name);
+            }
+        }
+        url = next;
+    }
+    Err(format!("Too many redirects from {}", first_url))
+}
+
+/// hyper keeps the cause of a failed request in the error's source chain.
+fn describe(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let cause_message = cause.to_string();
+        if !message.ends_with(&cause_message) {
+            message.push_str(": ");
+            message.push_str(&cause_message);
+        }
+        source = cause.source();
+    }
+    message
+}
+
+async fn evaluate_response(service: &Service, resp: Response<Incoming>, start: Instant) -> Attempt {
+    let code = resp.status();
+    if !status_accepted(service.expected_status_codes.as_deref(), code.as_u16()) {
+        return Attempt::down(start.elapsed().as_secs_f64(), format!("Unexpected status code {}", code)).with_status_code(code.as_u16());
+    }
+    if service.assertions.is_empty() {
+        return Attempt::up(start.elapsed().as_secs_f64()).with_status_code(code.as_u16());
+    }
+
+    // Assertions need the whole body, so latency includes reading it
+    let body = resp.into_body().collect().await;
+    let latency_seconds = start.elapsed().as_secs_f64();
+    let attempt = match body {
+        Ok(body) => match assertions::evaluate(&service.assertions, &String::from_utf8_lossy(&body.to_bytes())) {
+            Ok(()) => Attempt::up(latency_seconds),
+            Err(e) => Attempt::down(latency_seconds, e),
+        },
+        Err(e) => Attempt::down(latency_seconds, format!("Failed to read response body: {}", e)),
+    };
+    attempt.with_status_code(code.as_u16())
+}
+
+/// Any 2xx code is accepted unless a status code list is given.
+pub fn status_accepted(spec: Option<&str>, code: u16) -> bool {
+    match spec {
+        Some(spec) => parse_status_codes(spec).is_ok_and(|ranges| ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&code))),
+        None => (200..300).contains(&code),
+    }
+}
+
+/// Parses an accepted status code list such as `200-299,301,401` into
+/// inclusive ranges.
+pub fn parse_status_codes(spec: &str) -> Result<Vec<(u16, u16)>, String> {
+    let invalid = || format!("Invalid expected status codes {:?}", spec);
+    let ranges = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (lo, hi) = part.split_once('-').unwrap_or((part, part));
+            let lo: u16 = lo.trim().parse().map_err(|_| invalid())?;
+            let hi: u16 = hi.trim().parse().map_err(|_| invalid())?;
+            if !(100..=599).contains(&lo) || !(100..=599).contains(&hi) || lo > hi {
+                return Err(invalid());
+            }
+            Ok((lo, hi))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if ranges.is_empty() {
+        return Err(invalid());
+    }
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use axum::routing::any;
+    use axum::Router;
+    use chrono::{TimeZone, Utc};
+    use hyper_util::rt::TokioIo;
+    use hyper_util::service::TowerToHyperService;
+    use tokio::net::TcpListener;
+    use super::*;
+
+    fn service(url: &str) -> Service {
+        let now = Utc::now();
+        Service {
+            id: 1,
+            service_name: "api".to_string(),
+            healthcheck_url: url.to_string(),
+            healthcheck_duration_seconds: 30,
+            tags: sqlx::types::Json(Vec::new()),
+            cron_expression: None,
+            next_run_at: None,
+            check_type: "http".to_string(),
+            check_config: sqlx::types::Json(serde_json::json!({})),
+            heartbeat_token: None,
+            last_heartbeat_at: None,
+            slo_target: 99.9,
+            failure_threshold: 1,
+            recovery_threshold: 1,
+            timeout_seconds: None,
+            retries: None,
+            retry_backoff_ms: None,
+            tls_expiry_warning_days: None,
+            assertions: sqlx::types::Json(Vec::new()),
+            http_method: "GET".to_string(),
+            request_headers: sqlx::types::Json(BTreeMap::new()),
+            request_body: None,
+            expected_status_codes: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Redirects to the path after `/to/`, or to `localhost` on the same port
+    /// for `/to/localhost/...`. `/echo` answers with the method and body and
+    /// `/credentials` with the credentials it was sent.
+    fn app() -> Router {
+        let redirect = |status: StatusCode| {
+            any(move |request: Request<axum::body::Body>| async move {
+                let rest = request.uri().path().splitn(3, '/').nth(2).unwrap_or_default().to_string();
+                let location = match rest.strip_prefix("localhost/") {
+                    Some(path) => {
+                        let port = request.headers()[header::HOST].to_str().unwrap().rsplit(':').next().unwrap().to_string();
+                        format!("http://localhost:{}/{}", port, path)
+                    }
+                    None => format!("/{}", rest),
+                };
+                (status, [(header::LOCATION, location)])
+            })
+        };
+        Router::new()
+            .route("/301/*rest", redirect(StatusCode::MOVED_PERMANENTLY))
+            .route("/303/*rest", redirect(StatusCode::SEE_OTHER))
+            .route("/307/*rest", redirect(StatusCode::TEMPORARY_REDIRECT))
+            .route("/echo", any(|method: Method, body: String| async move { format!("{} {:?}", method, body) }))
+            .route(
+                "/credentials",
+                any(|headers: HeaderMap| async move {
+                    [header::AUTHORIZATION, header::COOKIE]
+                        .iter()
+                        .filter_map(|name| headers.get(name).map(|value| format!("{}: {}", name, value.to_str().unwrap())))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }),
+            )
+    }
+
+    /// Serves `app` on a local port, over TLS when given an acceptor, and
+    /// counts the connections accepted.
+    async fn serve(app: Router, tls: Option<tokio_native_tls::TlsAcceptor>) -> (SocketAddr, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let service = TowerToHyperService::new(app.clone());
+                let tls = tls.clone();
+                tokio::spawn(async move {
+                    let http = hyper::server::conn::http1::Builder::new();
+                    let _ = match tls {
+                        Some(tls) => http.serve_connection(TokioIo::new(tls.accept(stream).await.unwrap()), service).await,
+                        None => http.serve_connection(TokioIo::new(stream), service).await,
+                    };
+                });
+            }
+        });
+        (addr, connections)
+    }
+
+    async fn fetch(client: &HttpClient, service: &Service) -> String {
+        let body = send_service(client, service).await.unwrap().into_body().collect().await.unwrap();
+        String::from_utf8(body.to_bytes().to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn redirects_switch_to_get_except_307() {
+        let (addr, _) = serve(app(), None).await;
+        let client = client(Connector::default());
+
+        for (status, expected) in [("301", "GET \"\""), ("303", "GET \"\""), ("307", "POST \"ping\"")] {
+            let mut service = service(&format!("http://{}/{}/echo", addr, status));
+            service.http_method = "POST".to_string();
+            service.request_body = Some("ping".to_string());
+            service.request_headers.0.insert("Content-Type".to_string(), "text/plain".to_string());
+            assert_eq!(fetch(&client, &service).await, expected, "after a {}", status);
+        }
+    }
+
+    #[tokio::test]
+    async fn credentials_are_dropped_on_cross_host_redirects() {
+        let (addr, _) = serve(app(), None).await;
+        let client = client(Connector::default());
+        let mut service = service(&format!("http://{}/307/credentials", addr));
+        service.request_headers.0.insert("Authorization".to_string(), "Bearer secret".to_string());
+        service.request_headers.0.insert("Cookie".to_string(), "session=secret".to_string());
+
+        assert_eq!(fetch(&client, &service).await, "authorization: Bearer secret, cookie: session=secret");
+        service.healthcheck_url = format!("http://{}/307/localhost/credentials", addr);
+        assert_eq!(fetch(&client, &service).await, "");
+    }
+
+    #[tokio::test]
+    async fn connection_phases_are_timed_only_on_new_connections() {
+        let (addr, connections) = serve(app(), None).await;
+        let client = client(Connector::default());
+        let service = service(&format!("http://{}/echo", addr));
+
+        let first = check_with(&client, &service, Duration::from_secs(5)).await.timings.unwrap();
+        assert!(first.dns_ms.is_some() && first.connect_ms.is_some() && first.ttfb_ms.is_some());
+        assert_eq!(first.tls_ms, None);
+
+        let pooled = check_with(&client, &service, Duration::from_secs(5)).await.timings.unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!((pooled.dns_ms, pooled.connect_ms, pooled.tls_ms), (None, None, None));
+        assert!(pooled.ttfb_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn certificate_expiry_is_reported_on_pooled_connections() {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params.not_after = rcgen::date_time_ymd(2030, 1, 1);
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+
+        let identity = native_tls::Identity::from_pkcs8(cert.pem().as_bytes(), key.serialize_pem().as_bytes()).unwrap();
+        let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+        let (addr, connections) = serve(app(), Some(acceptor.into())).await;
+        let tls = native_tls::TlsConnector::builder()
+            .add_root_certificate(native_tls::Certificate::from_pem(cert.pem().as_bytes()).unwrap())
+            .build()
+            .unwrap();
+        let client = client(Connector::new(tls));
+        let service = service(&format!("https://localhost:{}/echo", addr.port()));
+        let expiry = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+
+        let first = check_with(&client, &service, Duration::from_secs(5)).await;
+        assert_eq!(first.status, "UP", "{:?}", first.error);
+        assert_eq!(first.tls_expires_at, Some(expiry));
+        assert!(first.timings.unwrap().tls_ms.is_some());
+
+        let pooled = check_with(&client, &service, Duration::from_secs(5)).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(pooled.tls_expires_at, Some(expiry));
+        assert_eq!(pooled.timings.unwrap().tls_ms, None);
+    }
+
+    #[test]
+    fn parses_codes_and_ranges() {
+        assert_eq!(parse_status_codes("200-299,301, 401").unwrap(), [(200, 299), (301, 301), (401, 401)]);
+        assert_eq!(parse_status_codes(" 404 ,").unwrap(), [(404, 404)]);
+    }
+
+    #[test]
+    fn rejects_invalid_codes() {
+        for spec in ["", ",", "abc", "200-", "299-200", "99", "600", "200-700"] {
+            assert!(parse_status_codes(spec).is_err(), "{:?} should be rejected", spec);
+        }
+    }
+}
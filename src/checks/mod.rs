@@ -0,0 +1,169 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use crate::config::CONFIG;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::models::Service;
+
+pub mod command;
+pub mod connector;
+pub mod dns;
+pub mod grpc;
+pub mod heartbeat;
+pub mod http;
+pub mod mysql;
+pub mod postgres;
+pub mod redis;
+pub mod smtp;
+pub mod tcp;
+pub mod tls;
+pub mod transaction;
+pub mod websocket;
+
+/// Supported values for `services.check_type`.
+pub const CHECK_TYPES: [&str; 13] = ["http", "tcp", "tls", "dns", "grpc", "redis", "postgres", "mysql", "smtp", "websocket", "heartbeat", "command", "transaction"];
+
+/// Result of a single attempt at checking a service.
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    pub status: &'static str,
+    pub latency_seconds: f64,
+    pub http_status_code: Option<u16>,
+    pub error: Option<String>,
+    pub tls_expires_at: Option<DateTime<Utc>>,
+    /// Per-step timings of a `transaction` check.
+    pub steps: Option<Vec<transaction::StepResult>>,
+    pub timings: Option<PhaseTimings>,
+}
+
+/// Where the time of a check went, in milliseconds. Phases a check does not
+/// go through are left out.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_ms: Option<f64>,
+    /// Until the response headers arrived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttfb_ms: Option<f64>,
+}
+
+impl PhaseTimings {
+    /// Phase names as used for the `phase` metric label.
+    pub const PHASES: [&'static str; 4] = ["dns", "connect", "tls", "ttfb"];
+
+    pub fn phases(&self) -> [(&'static str, Option<f64>); 4] {
+        let values = [self.dns_ms, self.connect_ms, self.tls_ms, self.ttfb_ms];
+        std::array::from_fn(|i| (Self::PHASES[i], values[i]))
+    }
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+impl Attempt {
+    pub fn up(latency_seconds: f64) -> Self {
+        Self { status: "UP", latency_seconds, http_status_code: None, error: None, tls_expires_at: None, steps: None, timings: None }
+    }
+
+    pub fn down(latency_seconds: f64, error: impl Into<String>) -> Self {
+        Self { status: "DOWN", latency_seconds, http_status_code: None, error: Some(error.into()), tls_expires_at: None, steps: None, timings: None }
+    }
+
+    pub fn with_status_code(mut self, code: u16) -> Self {
+        self.http_status_code = Some(code);
+        self
+    }
+
+    pub fn with_steps(mut self, steps: Vec<transaction::StepResult>) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+}
+
+/// Runs one attempt of the service's check type. A passing check becomes
+/// WARNING when its TLS certificate expires within the warning threshold.
+pub async fn attempt(service: &Service, timeout: Duration) -> Attempt {
+    let mut attempt = match service.check_type.as_str() {
+        "tcp" => tcp::check(&service.healthcheck_url, timeout).await,
+        "tls" => tls::check(&service.healthcheck_url, timeout).await,
+        "dns" => dns::check(&service.healthcheck_url, &service.check_config, timeout).await,
+        "grpc" => grpc::check(&service.healthcheck_url, &service.check_config, timeout).await,
+        "redis" => run_probe("Redis", timeout, redis::probe(&service.healthcheck_url, &service.check_config)).await,
+        "postgres" => run_probe("PostgreSQL", timeout, postgres::probe(&service.healthcheck_url, &service.check_config)).await,
+        "mysql" => run_probe("MySQL", timeout, mysql::probe(&service.healthcheck_url, &service.check_config)).await,
+        "smtp" => run_probe("SMTP", timeout, smtp::probe(&service.healthcheck_url)).await,
+        "heartbeat" => heartbeat::check(service),
+        "transaction" => transaction::check(&service.healthcheck_url, &service.check_config, timeout).await,
+        "command" => run_probe("Command", timeout, command::probe(&service.healthcheck_url, &service.check_config)).await,
+        "websocket" => run_probe("WebSocket", timeout, websocket::probe(&service.healthcheck_url, &service.check_config)).await,
+        _ => http::check(service, timeout).await,
+    };
+
+    if let (true, Some(expires_at)) = (attempt.status == "UP", attempt.tls_expires_at) {
+        let warning_days = service.tls_expiry_warning_days.unwrap_or(CONFIG.tls_expiry_warning_days);
+        let remaining = expires_at - Utc::now();
+        if remaining < chrono::Duration::days(warning_days) {
+            attempt.status = "WARNING";
+            attempt.error = Some(format!(
+                "TLS certificate expires in {} days ({})",
+                remaining.num_days(),
+                expires_at.format("%Y-%m-%d %H:%M:%S UTC")
+            ));
+        }
+    }
+    attempt
+}
+
+/// Checks that the check type is known and its target and `check_config`
+/// can be probed.
+pub fn validate(check_type: &str, target: &str, config: &Value) -> Result<(), String> {
+    reject_credentials(target)?;
+    match check_type {
+        "http" => Ok(()),
+        "tcp" => tcp::parse_target(target).map(|_| ()),
+        "tls" => tls::parse_target(target).map(|_| ()),
+        "dns" => dns::DnsConfig::parse(config).map(|_| ()),
+        "grpc" => grpc::endpoint(target).and(grpc::GrpcConfig::parse(config)).map(|_| ()),
+        "redis" => redis::parse_target(target).and(redis::RedisConfig::parse(config)).map(|_| ()),
+        "postgres" => postgres::validate(target, config),
+        "mysql" => mysql::validate(target, config),
+        "smtp" => smtp::parse_target(target).map(|_| ()),
+        "heartbeat" => heartbeat::HeartbeatConfig::parse(config).map(|_| ()),
+        "command" => command::validate(target, config),
+        "transaction" => transaction::validate(target, config),
+        "websocket" => websocket::validate_url(target).and(websocket::WebSocketConfig::parse(config)).map(|_| ()),
+        other => Err(format!("Unknown check type {}. Expected one of: {}", other, CHECK_TYPES.join(", "))),
+    }
+}
+
+/// `healthcheck_url` is exported as a metric label, sent with alerts and
+/// listed by the API, so it must not carry a username or password.
+pub fn reject_credentials(target: &str) -> Result<(), String> {
+    let Ok(url) = reqwest::Url::parse(target) else {
+        return Ok(());
+    };
+    if !url.username().is_empty() || url.password().is_some() || url.query_pairs().any(|(key, _)| key == "user" || key == "password") {
+        return Err("healthcheck_url must not contain credentials, set them in check_config or request_headers instead".to_string());
+    }
+    Ok(())
+}
+
+/// Runs a protocol probe under the check timeout; the whole exchange counts
+/// towards latency.
+async fn run_probe(protocol: &str, timeout: Duration, probe: impl Future<Output = Result<(), String>>) -> Attempt {
+    let start = Instant::now();
+    let result = tokio::time::timeout(timeout, probe).await;
+    let latency_seconds = start.elapsed().as_secs_f64();
+
+    match result {
+        Ok(Ok(())) => Attempt::up(latency_seconds),
+        Ok(Err(e)) => Attempt::down(latency_seconds, e),
+        Err(_) => Attempt::down(latency_seconds, format!("{} probe timed out", protocol)),
+    }
+}
@@ -0,0 +1,124 @@
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::mysql::MySqlConnectOptions;
+use sqlx::{Connection, MySqlConnection};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use super::tcp;
+
+/// `check_config` of a `mysql` service. Credentials live here rather than
+/// in the connection URL, which is shown in metrics and alerts.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MySqlConfig {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub database: Option<String>,
+}
+
+impl MySqlConfig {
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        serde_json::from_value(config.clone()).map_err(|e| format!("Invalid MySQL check config: {}", e))
+    }
+}
+
+fn is_url(target: &str) -> bool {
+    target.starts_with("mysql://") || target.starts_with("mariadb://")
+}
+
+/// Accepts `host:port` or a `mysql://host:port/database` URL.
+fn connect_options(target: &str) -> Result<MySqlConnectOptions, String> {
+    if is_url(target) {
+        target.parse().map_err(|e| format!("Invalid MySQL URL: {}", e))
+    } else {
+        let (host, port) = tcp::parse_target(target)?;
+        Ok(MySqlConnectOptions::new().host(&host).port(port))
+    }
+}
+
+pub fn validate(target: &str, config: &Value) -> Result<(), String> {
+    connect_options(target)?;
+    MySqlConfig::parse(config).map(|_| ())
+}
+
+/// With a `username` in the config, logs in and runs `SELECT 1`. Otherwise
+/// reads the server greeting and fails on an error packet such as
+/// "Host is not allowed to connect" or "Too many connections".
+pub async fn probe(target: &str, config: &Value) -> Result<(), String> {
+    super::reject_credentials(target)?;
+    let config = MySqlConfig::parse(config)?;
+    let mut options = connect_options(target)?;
+
+    if let Some(username) = &config.username {
+        options = options.username(username);
+        if let Some(password) = &config.password {
+            options = options.password(password);
+        }
+        if let Some(database) = &config.database {
+            options = options.database(database);
+        }
+        let mut conn = MySqlConnection::connect_with(&options).await.map_err(|e| format!("MySQL connection failed: {}", e))?;
+        sqlx::query("SELECT 1").execute(&mut conn).await.map_err(|e| format!("SELECT 1 failed: {}", e))?;
+        let _ = conn.close().await;
+        return Ok(());
+    }
+
+    let (host, port) = (options.get_host(), options.get_port());
+    let mut stream = TcpStream::connect((host, port)).await.map_err(|e| format!("Connection to {}:{} failed: {}", host, port, e))?;
+
+    // Packet header: 3-byte little-endian payload length and a sequence id
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await.map_err(|e| format!("No server greeting: {}", e))?;
+    let length = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload).await.map_err(|e| format!("Truncated server greeting: {}", e))?;
+
+    match payload.first() {
+        Some(10) => Ok(()),
+        Some(0xff) if payload.len() >= 3 => {
+            let code = u16::from_le_bytes([payload[1], payload[2]]);
+            Err(format!("MySQL error {}: {}", code, String::from_utf8_lossy(&payload[3..])))
+        }
+        _ => Err("Unexpected MySQL server greeting".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use super::*;
+
+    /// Sends `payload` as the first packet.
+    async fn fake_mysql(payload: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut packet = (payload.len() as u32).to_le_bytes()[..3].to_vec();
+            packet.push(0);
+            packet.extend(payload);
+            stream.write_all(&packet).await.unwrap();
+        });
+        target
+    }
+
+    #[tokio::test]
+    async fn accepts_a_handshake() {
+        let mut greeting = vec![10];
+        greeting.extend_from_slice(b"8.0.36\0");
+        greeting.extend_from_slice(&[0; 40]);
+        assert_eq!(probe(&fake_mysql(greeting).await, &serde_json::json!({})).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn reports_error_packets() {
+        let mut error = vec![0xff];
+        error.extend_from_slice(&1040u16.to_le_bytes());
+        error.extend_from_slice(b"Too many connections");
+        let url = format!("mysql://{}/app", fake_mysql(error).await);
+        assert_eq!(probe(&url, &serde_json::json!({})).await, Err("MySQL error 1040: Too many connections".to_string()));
+
+        assert_eq!(probe(&fake_mysql(b"HTTP/1.1 400".to_vec()).await, &serde_json::json!({})).await, Err("Unexpected MySQL server greeting".to_string()));
+    }
+}
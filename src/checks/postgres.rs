@@ -0,0 +1,112 @@
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::{Connection, PgConnection};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use super::tcp;
+
+/// Length 8 followed by the SSLRequest code 80877103.
+const SSL_REQUEST: [u8; 8] = [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f];
+
+/// `check_config` of a `postgres` service. Credentials live here rather than
+/// in the connection URL, which is shown in metrics and alerts.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PostgresConfig {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub database: Option<String>,
+}
+
+impl PostgresConfig {
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        serde_json::from_value(config.clone()).map_err(|e| format!("Invalid PostgreSQL check config: {}", e))
+    }
+}
+
+fn is_url(target: &str) -> bool {
+    target.starts_with("postgres://") || target.starts_with("postgresql://")
+}
+
+/// Accepts `host:port` or a `postgres://host:port/database` URL.
+fn connect_options(target: &str) -> Result<PgConnectOptions, String> {
+    if is_url(target) {
+        target.parse().map_err(|e| format!("Invalid PostgreSQL URL: {}", e))
+    } else {
+        let (host, port) = tcp::parse_target(target)?;
+        Ok(PgConnectOptions::new_without_pgpass().host(&host).port(port))
+    }
+}
+
+pub fn validate(target: &str, config: &Value) -> Result<(), String> {
+    connect_options(target)?;
+    PostgresConfig::parse(config).map(|_| ())
+}
+
+/// With a `username` in the config, logs in and runs `SELECT 1`. Otherwise
+/// sends an SSLRequest and expects the server to accept or decline it,
+/// which only a PostgreSQL server does.
+pub async fn probe(target: &str, config: &Value) -> Result<(), String> {
+    super::reject_credentials(target)?;
+    let config = PostgresConfig::parse(config)?;
+    let mut options = connect_options(target)?;
+
+    if let Some(username) = &config.username {
+        options = options.username(username);
+        if let Some(password) = &config.password {
+            options = options.password(password);
+        }
+        if let Some(database) = &config.database {
+            options = options.database(database);
+        }
+        let mut conn = PgConnection::connect_with(&options).await.map_err(|e| format!("PostgreSQL connection failed: {}", e))?;
+        sqlx::query("SELECT 1").execute(&mut conn).await.map_err(|e| format!("SELECT 1 failed: {}", e))?;
+        let _ = conn.close().await;
+        return Ok(());
+    }
+
+    let (host, port) = (options.get_host(), options.get_port());
+    let mut stream = TcpStream::connect((host, port)).await.map_err(|e| format!("Connection to {}:{} failed: {}", host, port, e))?;
+    stream.write_all(&SSL_REQUEST).await.map_err(|e| e.to_string())?;
+    let mut reply = [0u8; 1];
+    stream.read_exact(&mut reply).await.map_err(|e| format!("No reply to SSLRequest: {}", e))?;
+
+    match reply[0] {
+        b'S' | b'N' => Ok(()),
+        other => Err(format!("Unexpected reply to SSLRequest: {:#04x}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use super::*;
+
+    /// Expects an SSLRequest and answers it with `reply`.
+    async fn fake_postgres(reply: u8) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 8];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, SSL_REQUEST);
+            stream.write_all(&[reply]).await.unwrap();
+        });
+        target
+    }
+
+    #[tokio::test]
+    async fn accepts_an_answered_ssl_request() {
+        assert_eq!(probe(&fake_postgres(b'N').await, &serde_json::json!({})).await, Ok(()));
+        let url = format!("postgres://{}/app", fake_postgres(b'S').await);
+        assert_eq!(probe(&url, &serde_json::json!({})).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn rejects_other_servers() {
+        // An HTTP server answers with "HTTP/1.1 400"
+        assert_eq!(probe(&fake_postgres(b'H').await, &serde_json::json!({})).await, Err("Unexpected reply to SSLRequest: 0x48".to_string()));
+    }
+}
@@ -0,0 +1,120 @@
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use super::tcp;
+
+/// `check_config` of a `redis` service.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedisConfig {
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl RedisConfig {
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        serde_json::from_value(config.clone()).map_err(|e| format!("Invalid Redis check config: {}", e))
+    }
+}
+
+pub fn parse_target(target: &str) -> Result<(String, u16), String> {
+    tcp::parse_target(target.strip_prefix("redis://").unwrap_or(target))
+}
+
+/// Sends a command and returns the first line of the reply.
+async fn exchange(stream: &mut BufReader<TcpStream>, args: &[&str]) -> Result<String, String> {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    stream.get_mut().write_all(request.as_bytes()).await.map_err(|e| e.to_string())?;
+
+    let mut reply = String::new();
+    stream.read_line(&mut reply).await.map_err(|e| e.to_string())?;
+    Ok(reply.trim_end().to_string())
+}
+
+/// Authenticates if configured and expects `PING` to answer `PONG`.
+pub async fn probe(target: &str, config: &Value) -> Result<(), String> {
+    let config = RedisConfig::parse(config)?;
+    let (host, port) = parse_target(target)?;
+    let stream = TcpStream::connect((host.as_str(), port)).await.map_err(|e| format!("Connection to {}:{} failed: {}", host, port, e))?;
+    let mut stream = BufReader::new(stream);
+
+    if let Some(password) = &config.password {
+        let mut args = vec!["AUTH"];
+        args.extend(config.username.as_deref());
+        args.push(password);
+        let reply = exchange(&mut stream, &args).await?;
+        if !reply.starts_with('+') {
+            return Err(format!("Redis AUTH failed: {}", reply.trim_start_matches('-')));
+        }
+    }
+
+    match exchange(&mut stream, &["PING"]).await?.as_str() {
+        "+PONG" => Ok(()),
+        "" => Err("Redis closed the connection".to_string()),
+        reply => Err(format!("Unexpected reply to PING: {}", reply.trim_start_matches(['-', '+']))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use super::*;
+
+    /// Answers each command with the next reply, reporting the commands
+    /// received with their arguments joined by spaces.
+    async fn fake_redis(replies: &'static [&'static str]) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let (commands, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            for reply in replies {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                let count: usize = line.trim_end().trim_start_matches('*').parse().unwrap();
+                let mut args = Vec::new();
+                for _ in 0..count {
+                    let (mut length, mut arg) = (String::new(), String::new());
+                    stream.read_line(&mut length).await.unwrap();
+                    stream.read_line(&mut arg).await.unwrap();
+                    args.push(arg.trim_end().to_string());
+                }
+                let _ = commands.send(args.join(" "));
+                stream.get_mut().write_all(format!("{}\r\n", reply).as_bytes()).await.unwrap();
+            }
+        });
+        (target, received)
+    }
+
+    #[tokio::test]
+    async fn pings_without_credentials() {
+        let (target, mut commands) = fake_redis(&["+PONG"]).await;
+        assert_eq!(probe(&format!("redis://{}", target), &serde_json::json!({})).await, Ok(()));
+        assert_eq!(commands.recv().await.unwrap(), "PING");
+    }
+
+    #[tokio::test]
+    async fn authenticates_before_pinging() {
+        let (target, mut commands) = fake_redis(&["+OK", "+PONG"]).await;
+        let config = serde_json::json!({"username": "monitor", "password": "secret"});
+        assert_eq!(probe(&target, &config).await, Ok(()));
+        assert_eq!(commands.recv().await.unwrap(), "AUTH monitor secret");
+        assert_eq!(commands.recv().await.unwrap(), "PING");
+    }
+
+    #[tokio::test]
+    async fn fails_on_error_replies() {
+        let (target, _) = fake_redis(&["-WRONGPASS invalid username-password pair"]).await;
+        let config = serde_json::json!({"password": "wrong"});
+        assert_eq!(probe(&target, &config).await, Err("Redis AUTH failed: WRONGPASS invalid username-password pair".to_string()));
+
+        let (target, _) = fake_redis(&["-NOAUTH Authentication required."]).await;
+        assert_eq!(probe(&target, &serde_json::json!({})).await, Err("Unexpected reply to PING: NOAUTH Authentication required.".to_string()));
+    }
+}
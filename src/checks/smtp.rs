@@ -0,0 +1,81 @@
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use super::tcp;
+
+pub fn parse_target(target: &str) -> Result<(String, u16), String> {
+    tcp::parse_target(target.strip_prefix("smtp://").unwrap_or(target))
+}
+
+/// Reads a possibly multi-line reply and returns its code and last line.
+async fn read_reply(stream: &mut BufReader<TcpStream>) -> Result<(u16, String), String> {
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await.map_err(|e| e.to_string())? == 0 {
+            return Err("SMTP server closed the connection".to_string());
+        }
+        let line = line.trim_end();
+        // "250-..." continues the reply, "250 ..." ends it
+        if line.as_bytes().get(3) != Some(&b'-') {
+            let code = line.get(..3).and_then(|code| code.parse().ok()).ok_or_else(|| format!("Invalid SMTP reply: {}", line))?;
+            return Ok((code, line.to_string()));
+        }
+    }
+}
+
+/// Expects a `220` banner and a `250` reply to `EHLO`.
+pub async fn probe(target: &str) -> Result<(), String> {
+    let (host, port) = parse_target(target)?;
+    let stream = TcpStream::connect((host.as_str(), port)).await.map_err(|e| format!("Connection to {}:{} failed: {}", host, port, e))?;
+    let mut stream = BufReader::new(stream);
+
+    let (code, line) = read_reply(&mut stream).await?;
+    if code != 220 {
+        return Err(format!("Unexpected SMTP banner: {}", line));
+    }
+
+    stream.get_mut().write_all(b"EHLO nx-health-checker\r\n").await.map_err(|e| e.to_string())?;
+    let (code, line) = read_reply(&mut stream).await?;
+    if code != 250 {
+        return Err(format!("EHLO rejected: {}", line));
+    }
+
+    let _ = stream.get_mut().write_all(b"QUIT\r\n").await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use super::*;
+
+    /// Sends `banner`, then answers the first command with `ehlo_reply`.
+    async fn fake_smtp(banner: &'static str, ehlo_reply: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            stream.get_mut().write_all(banner.as_bytes()).await.unwrap();
+            let mut command = String::new();
+            stream.read_line(&mut command).await.unwrap();
+            assert_eq!(command, "EHLO nx-health-checker\r\n");
+            stream.get_mut().write_all(ehlo_reply.as_bytes()).await.unwrap();
+        });
+        target
+    }
+
+    #[tokio::test]
+    async fn accepts_a_multi_line_ehlo_reply() {
+        let target = fake_smtp("220-mail.example.com ESMTP\r\n220 ready\r\n", "250-mail.example.com\r\n250-STARTTLS\r\n250 8BITMIME\r\n").await;
+        assert_eq!(probe(&format!("smtp://{}", target)).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn fails_on_rejections() {
+        let target = fake_smtp("554 No SMTP service here\r\n", "").await;
+        assert_eq!(probe(&target).await, Err("Unexpected SMTP banner: 554 No SMTP service here".to_string()));
+
+        let target = fake_smtp("220 ready\r\n", "421 Too busy\r\n").await;
+        assert_eq!(probe(&target).await, Err("EHLO rejected: 421 Too busy".to_string()));
+    }
+}
@@ -0,0 +1,62 @@
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use super::{elapsed_ms, Attempt, PhaseTimings};
+
+/// Splits a `host:port` or `tcp://host:port` target. IPv6 hosts must be
+/// bracketed, e.g. `[::1]:5432`.
+pub fn parse_target(target: &str) -> Result<(String, u16), String> {
+    let address = target.strip_prefix("tcp://").unwrap_or(target).trim_end_matches('/');
+    let invalid = || format!("Invalid TCP target {:?}, expected host:port", target);
+
+    let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port: u16 = port.parse().map_err(|_| invalid())?;
+    if host.is_empty() || port == 0 {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Resolves the host and connects to the first address that accepts,
+/// recording both phases. Phases completed before a failure or timeout are
+/// still recorded.
+pub async fn connect(host: &str, port: u16, timings: &mut PhaseTimings) -> io::Result<TcpStream> {
+    let start = Instant::now();
+    let addresses: Vec<_> = tokio::net::lookup_host((host, port)).await?.collect();
+    timings.dns_ms = Some(elapsed_ms(start));
+
+    let start = Instant::now();
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("{} did not resolve to any address", host));
+    for address in addresses {
+        match TcpStream::connect(address).await {
+            Ok(stream) => {
+                timings.connect_ms = Some(elapsed_ms(start));
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Opens a TCP connection and reports the connect latency, including name
+/// resolution.
+pub async fn check(target: &str, timeout: Duration) -> Attempt {
+    let start = Instant::now();
+    let (host, port) = match parse_target(target) {
+        Ok(address) => address,
+        Err(e) => return Attempt::down(0.0, e),
+    };
+
+    let mut timings = PhaseTimings::default();
+    let result = tokio::time::timeout(timeout, connect(&host, port, &mut timings)).await;
+    let latency_seconds = start.elapsed().as_secs_f64();
+    let mut attempt = match result {
+        Ok(Ok(_)) => Attempt::up(latency_seconds),
+        Ok(Err(e)) => Attempt::down(latency_seconds, format!("Connection to {}:{} failed: {}", host, port, e)),
+        Err(_) => Attempt::down(latency_seconds, format!("Connection to {}:{} timed out", host, port)),
+    };
+    attempt.timings = Some(timings);
+    attempt
+}
@@ -0,0 +1,157 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use tokio_rustls::TlsConnector;
+use super::{elapsed_ms, tcp, Attempt, PhaseTimings};
+
+static PROVIDER: Lazy<Arc<CryptoProvider>> = Lazy::new(|| Arc::new(rustls::crypto::ring::default_provider()));
+
+/// The system trust store, honouring `SSL_CERT_FILE` and `SSL_CERT_DIR`, so
+/// `tls` checks trust the same certificates as `http` checks.
+static ROOTS: Lazy<Arc<RootCertStore>> = Lazy::new(|| {
+    let native = rustls_native_certs::load_native_certs();
+    for e in &native.errors {
+        tracing::warn!("Failed to load system CA certificates: {}", e);
+    }
+    let mut roots = RootCertStore::empty();
+    let (_, ignored) = roots.add_parsable_certificates(native.certs);
+    if ignored > 0 {
+        tracing::warn!("Ignored {} unparsable system CA certificates", ignored);
+    }
+    Arc::new(roots)
+});
+
+/// Leaf certificate presented by a server.
+#[derive(Debug, Clone)]
+pub struct PeerCertificate {
+    pub not_after: DateTime<Utc>,
+    /// Why the chain is not trusted, if it isn't. Expired certificates end up here too.
+    pub verification_error: Option<String>,
+}
+
+/// Accepts any certificate so expired or untrusted chains can still be
+/// inspected, while recording what the standard verifier made of it.
+#[derive(Debug)]
+struct RecordingVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    error: parking_lot::Mutex<Option<String>>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Err(e) = self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now) {
+            *self.error.lock() = Some(e.to_string());
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Performs a TLS handshake with `host:port` on a new connection and returns
+/// the leaf certificate. The connection phases are recorded in `timings`.
+pub async fn probe(host: &str, port: u16, timeout: Duration, timings: &mut PhaseTimings) -> Result<PeerCertificate, String> {
+    let inner = WebPkiServerVerifier::builder_with_provider(ROOTS.clone(), PROVIDER.clone())
+        .build()
+        .map_err(|e| e.to_string())?;
+    let verifier = Arc::new(RecordingVerifier { inner, error: parking_lot::Mutex::new(None) });
+    let config = ClientConfig::builder_with_provider(PROVIDER.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(host.to_string()).map_err(|e| format!("Invalid server name {}: {}", host, e))?;
+
+    let handshake = async {
+        let tcp = tcp::connect(host, port, timings).await?;
+        let start = Instant::now();
+        let stream = TlsConnector::from(Arc::new(config)).connect(server_name, tcp).await?;
+        timings.tls_ms = Some(elapsed_ms(start));
+        Ok::<_, std::io::Error>(stream)
+    };
+    let stream = tokio::time::timeout(timeout, handshake)
+        .await
+        .map_err(|_| format!("TLS handshake with {}:{} timed out", host, port))?
+        .map_err(|e| format!("TLS handshake with {}:{} failed: {}", host, port, e))?;
+
+    let leaf = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or("Server did not present a certificate")?;
+    let not_after = certificate_expiry(leaf)?;
+
+    let verification_error = verifier.error.lock().take();
+    Ok(PeerCertificate { not_after, verification_error })
+}
+
+/// Reads the expiry of a DER encoded certificate.
+pub fn certificate_expiry(der: &[u8]) -> Result<DateTime<Utc>, String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).map_err(|e| format!("Invalid certificate: {}", e))?;
+    DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0).ok_or_else(|| "Certificate expiry is out of range".to_string())
+}
+
+/// Splits a `host:port` or `tls://host:port` target.
+pub fn parse_target(target: &str) -> Result<(String, u16), String> {
+    tcp::parse_target(target.strip_prefix("tls://").unwrap_or(target))
+}
+
+/// Handshakes with the target, failing on untrusted or expired certificates.
+pub async fn check(target: &str, timeout: Duration) -> Attempt {
+    let start = Instant::now();
+    let (host, port) = match parse_target(target) {
+        Ok(address) => address,
+        Err(e) => return Attempt::down(0.0, e),
+    };
+
+    let mut timings = PhaseTimings::default();
+    let mut attempt = match probe(&host, port, timeout, &mut timings).await {
+        Ok(cert) => {
+            let latency_seconds = start.elapsed().as_secs_f64();
+            let mut attempt = match cert.verification_error {
+                Some(e) => Attempt::down(latency_seconds, format!("Certificate verification failed: {}", e)),
+                None => Attempt::up(latency_seconds),
+            };
+            attempt.tls_expires_at = Some(cert.not_after);
+            attempt
+        }
+        Err(e) => Attempt::down(start.elapsed().as_secs_f64(), e),
+    };
+    attempt.timings = Some(timings);
+    attempt
+}
@@ -0,0 +1,313 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+use http_body_util::BodyExt;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::cookie::Jar;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use crate::assertions::{self, Assertion};
+use super::{http, Attempt};
+
+/// `{{name}}` placeholders in step URLs, headers and bodies.
+static VARIABLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap());
+
+/// `check_config` of a `transaction` service.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransactionConfig {
+    pub steps: Vec<Step>,
+}
+
+/// One request of a transaction. Relative URLs are resolved against the
+/// service's `healthcheck_url`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub name: Option<String>,
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+    pub expected_status_codes: Option<String>,
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+    /// Variable name to JSONPath into the response body.
+    #[serde(default)]
+    pub extract: BTreeMap<String, String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// Timing of a step that was run, stored with the check result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepResult {
+    pub name: String,
+    pub http_status_code: Option<u16>,
+    pub latency_ms: f64,
+}
+
+impl TransactionConfig {
+    /// Also rejects templates that use a variable before a step extracts it.
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        let config: Self = serde_json::from_value(config.clone()).map_err(|e| format!("Invalid transaction check config: {}", e))?;
+        if config.steps.is_empty() {
+            return Err("A transaction needs at least one step".to_string());
+        }
+
+        let mut defined = BTreeSet::new();
+        for (index, step) in config.steps.iter().enumerate() {
+            let name = step.display_name(index);
+            let invalid = |e: String| format!("{}: {}", name, e);
+            reqwest::Method::from_bytes(step.method.as_bytes()).map_err(|_| invalid(format!("Invalid HTTP method {}", step.method)))?;
+            if let Some(spec) = &step.expected_status_codes {
+                http::parse_status_codes(spec).map_err(invalid)?;
+            }
+            for assertion in &step.assertions {
+                assertion.validate().map_err(invalid)?;
+            }
+            let templates = std::iter::once(&step.url).chain(step.headers.values()).chain(step.body.as_ref());
+            for template in templates {
+                for captures in VARIABLE.captures_iter(template) {
+                    if !defined.contains(&captures[1]) {
+                        return Err(invalid(format!("Variable {} is not extracted by an earlier step", &captures[1])));
+                    }
+                }
+            }
+            for (variable, path) in &step.extract {
+                JsonPath::parse(path).map_err(|e| invalid(format!("Invalid JSONPath {}: {}", path, e)))?;
+                defined.insert(variable.as_str());
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl Step {
+    fn display_name(&self, index: usize) -> String {
+        self.name.clone().unwrap_or_else(|| format!("step {}", index + 1))
+    }
+}
+
+/// The base URL is required even when every step uses an absolute URL.
+pub fn validate(base_url: &str, config: &Value) -> Result<(), String> {
+    reqwest::Url::parse(base_url).map_err(|e| format!("Invalid base URL {}: {}", base_url, e))?;
+    TransactionConfig::parse(config).map(|_| ())
+}
+
+/// Runs the steps in order, stopping at the first failure. Requests go
+/// through the pooled client of `http` checks with a cookie jar kept for
+/// the whole transaction, and every step gets the check timeout.
+pub async fn check(base_url: &str, config: &Value, timeout: Duration) -> Attempt {
+    let base = match reqwest::Url::parse(base_url) {
+        Ok(base) => base,
+        Err(e) => return Attempt::down(0.0, format!("Invalid base URL {}: {}", base_url, e)),
+    };
+    let config = match TransactionConfig::parse(config) {
+        Ok(config) => config,
+        Err(e) => return Attempt::down(0.0, e),
+    };
+    let cookies = Jar::default();
+
+    let start = Instant::now();
+    let mut variables = BTreeMap::new();
+    let mut results = Vec::new();
+    let mut status_code = None;
+    for (index, step) in config.steps.iter().enumerate() {
+        let name = step.display_name(index);
+        let step_start = Instant::now();
+        let outcome = tokio::time::timeout(timeout, run_step(&cookies, &base, step, &mut variables))
+            .await
+            .unwrap_or_else(|_| Err((None, "HTTP request timed out".to_string())));
+        status_code = match &outcome {
+            Ok(code) => Some(*code),
+            Err((code, _)) => *code,
+        };
+        results.push(StepResult {
+            name: name.clone(),
+            http_status_code: status_code,
+            latency_ms: step_start.elapsed().as_secs_f64() * 1000.0,
+        });
+
+        if let Err((_, e)) = outcome {
+            let mut attempt = Attempt::down(start.elapsed().as_secs_f64(), format!("{}: {}", name, e)).with_steps(results);
+            attempt.http_status_code = status_code;
+            return attempt;
+        }
+    }
+
+    let mut attempt = Attempt::up(start.elapsed().as_secs_f64()).with_steps(results);
+    attempt.http_status_code = status_code;
+    attempt
+}
+
+/// Sends one step and stores its extracted variables. Returns the status
+/// code, which failures also carry once a response has arrived.
+async fn run_step(
+    cookies: &Jar,
+    base: &reqwest::Url,
+    step: &Step,
+    variables: &mut BTreeMap<String, String>,
+) -> Result<u16, (Option<u16>, String)> {
+    let fail = |e: String| (None, e);
+    let url = base.join(&render(&step.url, variables)).map_err(|e| fail(format!("Invalid URL: {}", e)))?;
+    let headers = step.headers.iter().map(|(name, value)| (name.clone(), render(value, variables))).collect();
+    let body = step.body.as_ref().map(|body| render(body, variables)).unwrap_or_default();
+
+    let resp = http::request(&step.method, url.as_str(), &headers, body, cookies).await.map_err(fail)?;
+    let code = resp.status().as_u16();
+    let fail = |e: String| (Some(code), e);
+    if !http::status_accepted(step.expected_status_codes.as_deref(), code) {
+        return Err(fail(format!("Unexpected status code {}", code)));
+    }
+    if step.assertions.is_empty() && step.extract.is_empty() {
+        return Ok(code);
+    }
+
+    let body = resp.into_body().collect().await.map_err(|e| fail(format!("Failed to read response body: {}", e)))?.to_bytes();
+    let body = String::from_utf8_lossy(&body);
+    assertions::evaluate(&step.assertions, &body).map_err(fail)?;
+    if !step.extract.is_empty() {
+        let json: Value = serde_json::from_str(&body).map_err(|e| fail(format!("Cannot extract variables, body is not JSON: {}", e)))?;
+        for (variable, path) in &step.extract {
+            let query = JsonPath::parse(path).map_err(|e| fail(e.to_string()))?;
+            let value = match query.query(&json).all().first() {
+                Some(Value::String(text)) => text.clone(),
+                Some(value) => value.to_string(),
+                None => return Err(fail(format!("Cannot extract {}, {} matched nothing", variable, path))),
+            };
+            variables.insert(variable.clone(), value);
+        }
+    }
+    Ok(code)
+}
+
+fn render(template: &str, variables: &BTreeMap<String, String>) -> String {
+    VARIABLE
+        .replace_all(template, |captures: &regex::Captures| variables.get(&captures[1]).cloned().unwrap_or_default())
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::Path;
+    use axum::http::{header, HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use tokio::net::TcpListener;
+    use super::*;
+
+    /// `POST /login` sets a session cookie and redirects to `/token`, which
+    /// hands out a token to sessions. `/users/{id}` needs both.
+    async fn fake_app() -> String {
+        let has_session = |headers: &HeaderMap| headers.get(header::COOKIE).is_some_and(|cookie| cookie == "session=abc");
+        let app = Router::new()
+            .route(
+                "/login",
+                post(|| async { (StatusCode::SEE_OTHER, [(header::LOCATION, "/token"), (header::SET_COOKIE, "session=abc; Path=/")]) }),
+            )
+            .route(
+                "/token",
+                get(move |headers: HeaderMap| async move {
+                    if !has_session(&headers) {
+                        return StatusCode::UNAUTHORIZED.into_response();
+                    }
+                    Json(serde_json::json!({"access_token": "t1", "user": {"id": 7}})).into_response()
+                }),
+            )
+            .route(
+                "/users/:id",
+                get(move |Path(id): Path<i64>, headers: HeaderMap| async move {
+                    if !has_session(&headers) || headers.get(header::AUTHORIZATION).is_none_or(|token| token != "Bearer t1") {
+                        return StatusCode::UNAUTHORIZED.into_response();
+                    }
+                    Json(serde_json::json!({"id": id, "active": true})).into_response()
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base
+    }
+
+    fn login_then(profile: Value) -> Value {
+        serde_json::json!({"steps": [
+            {"name": "login", "method": "POST", "url": "login", "extract": {"token": "$.access_token", "user_id": "$.user.id"}},
+            profile,
+        ]})
+    }
+
+    fn step_codes(attempt: &Attempt) -> Vec<(String, Option<u16>)> {
+        attempt.steps.iter().flatten().map(|step| (step.name.clone(), step.http_status_code)).collect()
+    }
+
+    #[tokio::test]
+    async fn passes_variables_and_cookies_between_steps() {
+        let base = fake_app().await;
+        let config = login_then(serde_json::json!({
+            "name": "profile",
+            "url": "users/{{user_id}}",
+            "headers": {"Authorization": "Bearer {{token}}"},
+            "assertions": [{"type": "json_path", "path": "$.id", "equals": 7}],
+        }));
+
+        let attempt = check(&base, &config, Duration::from_secs(5)).await;
+        assert_eq!(attempt.status, "UP", "{:?}", attempt.error);
+        assert_eq!(step_codes(&attempt), [("login".to_string(), Some(200)), ("profile".to_string(), Some(200))]);
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_first_failing_step() {
+        let base = fake_app().await;
+        let mut config = login_then(serde_json::json!({"name": "profile", "url": "users/{{user_id}}"}));
+        config["steps"].as_array_mut().unwrap().push(serde_json::json!({"name": "never", "url": "token"}));
+
+        let attempt = check(&base, &config, Duration::from_secs(5)).await;
+        assert_eq!(attempt.error.as_deref(), Some("profile: Unexpected status code 401"));
+        assert_eq!(attempt.http_status_code, Some(401));
+        assert_eq!(step_codes(&attempt), [("login".to_string(), Some(200)), ("profile".to_string(), Some(401))]);
+    }
+
+    #[tokio::test]
+    async fn cookies_do_not_outlive_a_run() {
+        let base = fake_app().await;
+        let login = serde_json::json!({"steps": [{"method": "POST", "url": "login"}]});
+        assert_eq!(check(&base, &login, Duration::from_secs(5)).await.status, "UP");
+
+        let token = serde_json::json!({"steps": [{"url": "token"}]});
+        assert_eq!(check(&base, &token, Duration::from_secs(5)).await.error.as_deref(), Some("step 1: Unexpected status code 401"));
+    }
+
+    #[test]
+    fn rejects_variables_used_before_they_are_extracted() {
+        let error = |config: Value| TransactionConfig::parse(&config).unwrap_err();
+
+        let config = serde_json::json!({"steps": [{"url": "users/{{user_id}}"}]});
+        assert_eq!(error(config), "step 1: Variable user_id is not extracted by an earlier step");
+
+        // A step cannot use what it extracts itself
+        let config = serde_json::json!({"steps": [{"name": "login", "url": "login", "body": "{{ token }}", "extract": {"token": "$.token"}}]});
+        assert_eq!(error(config), "login: Variable token is not extracted by an earlier step");
+
+        let config = serde_json::json!({"steps": [
+            {"url": "login", "extract": {"token": "$.token"}},
+            {"url": "me", "headers": {"Authorization": "Bearer {{token}}", "X-Request": "{{request_id}}"}},
+        ]});
+        assert_eq!(error(config), "step 2: Variable request_id is not extracted by an earlier step");
+
+        assert!(TransactionConfig::parse(&login_then(serde_json::json!({"url": "users/{{user_id}}"}))).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_steps() {
+        assert_eq!(TransactionConfig::parse(&serde_json::json!({"steps": []})).unwrap_err(), "A transaction needs at least one step");
+        let config = serde_json::json!({"steps": [{"url": "login", "extract": {"token": "token"}}]});
+        assert!(TransactionConfig::parse(&config).unwrap_err().starts_with("step 1: Invalid JSONPath token"));
+    }
+}
@@ -0,0 +1,116 @@
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::Message;
+
+/// `check_config` of a `websocket` service.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// Text message sent once the connection is open.
+    pub send: Option<String>,
+    /// Text the first reply (or greeting, without `send`) must contain.
+    pub expect: Option<String>,
+}
+
+impl WebSocketConfig {
+    pub fn parse(config: &Value) -> Result<Self, String> {
+        serde_json::from_value(config.clone()).map_err(|e| format!("Invalid WebSocket check config: {}", e))
+    }
+}
+
+pub fn validate_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "ws" || parsed.scheme() == "wss" => Ok(()),
+        _ => Err(format!("Invalid WebSocket URL {}, expected ws:// or wss://", url)),
+    }
+}
+
+/// Completes the upgrade handshake and, if configured, exchanges a message.
+pub async fn probe(url: &str, config: &Value) -> Result<(), String> {
+    let config = WebSocketConfig::parse(config)?;
+    let (mut socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|e| format!("WebSocket handshake failed: {}", e))?;
+
+    if let Some(message) = &config.send {
+        socket.send(Message::text(message.as_str())).await.map_err(|e| format!("Failed to send message: {}", e))?;
+    }
+
+    if config.send.is_some() || config.expect.is_some() {
+        let reply = loop {
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => break text,
+                Some(Ok(Message::Binary(data))) => break String::from_utf8_lossy(&data).into_owned(),
+                Some(Ok(Message::Close(_))) | None => return Err("WebSocket closed before replying".to_string()),
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(format!("Failed to read reply: {}", e)),
+            }
+        };
+        if let Some(expected) = &config.expect {
+            if !reply.contains(expected.as_str()) {
+                return Err(format!("Reply {:?} does not contain {:?}", reply, expected));
+            }
+        }
+    }
+
+    let _ = socket.close(None).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use super::*;
+
+    /// Greets each client with a ping, then answers every text message with
+    /// `echo: <message>`.
+    async fn fake_echo_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/socket", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    let _ = socket.send(Message::Ping(Vec::new())).await;
+                    while let Some(Ok(message)) = socket.next().await {
+                        if let Message::Text(text) = message {
+                            let _ = socket.send(Message::text(format!("echo: {}", text))).await;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn completes_the_handshake() {
+        let url = fake_echo_server().await;
+        assert_eq!(probe(&url, &serde_json::json!({})).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn checks_the_reply_to_a_message() {
+        let url = fake_echo_server().await;
+        assert_eq!(probe(&url, &serde_json::json!({"send": "ping", "expect": "echo: ping"})).await, Ok(()));
+        assert_eq!(
+            probe(&url, &serde_json::json!({"send": "ping", "expect": "pong"})).await,
+            Err("Reply \"echo: ping\" does not contain \"pong\"".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_server_does_not_upgrade() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/socket", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n").await;
+        });
+        let error = probe(&url, &serde_json::json!({})).await.unwrap_err();
+        assert!(error.starts_with("WebSocket handshake failed"), "{}", error);
+    }
+}
@@ -1,97 +1,509 @@
-use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::{task::JoinHandle, time::sleep};
+use chrono::{DateTime, Utc};
+use tokio::{sync::{Notify, Semaphore}, task::JoinHandle, time::sleep};
 use sqlx::SqlitePool;
+use crate::config::CONFIG;
 use crate::models::Service;
+use crate::notifications::{self, StateChange};
+use crate::{checks, maintenance, schedule};
 use once_cell::sync::Lazy;
 
-pub static GLOBAL_COLLECTOR: Lazy<CollectorManager> = Lazy::new(|| CollectorManager::new());
-use crate::metrics::{SERVICE_STATUS, SERVICE_LATENCY_SECONDS, SERVICE_CHECKS_TOTAL};
+pub static GLOBAL_COLLECTOR: Lazy<CollectorManager> = Lazy::new(CollectorManager::new);
+use crate::metrics::{self, SERVICE_STATUS, SERVICE_LATENCY_SECONDS, SERVICE_LATENCY_HISTOGRAM, SERVICE_PHASE_HISTOGRAM, SERVICE_CHECKS_TOTAL, SERVICE_TLS_CERT_EXPIRY_DAYS, SERVICE_IN_MAINTENANCE};
 
+/// Schedules the checks of all services from a single task and keeps the
+/// schedule in line with the `services` table.
 pub struct CollectorManager {
-    running_flag: Arc<AtomicBool>,
-    handles: parking_lot::Mutex<Vec<JoinHandle<()>>>,
+    schedule: parking_lot::Mutex<HashMap<i64, ScheduledService>>,
+    /// Wakes the scheduler when the schedule changed.
+    wake: Notify,
+    scheduler: parking_lot::Mutex<Option<JoinHandle<()>>>,
+}
+
+struct ScheduledService {
+    service: Arc<Service>,
+    /// `None` once a cron expression has no further occurrences.
+    next_run_at: Option<DateTime<Utc>>,
+    /// Whether `next_run_at` still has to be written to the database.
+    unsaved: bool,
+    running: Option<JoinHandle<()>>,
+}
+
+impl ScheduledService {
+    fn abort(&self) {
+        if let Some(running) = &self.running {
+            running.abort();
+        }
+    }
 }
 
 impl CollectorManager {
     pub fn new() -> Self {
         Self {
-            running_flag: Arc::new(AtomicBool::new(false)),
-            handles: parking_lot::Mutex::new(Vec::new()),
+            schedule: parking_lot::Mutex::new(HashMap::new()),
+            wake: Notify::new(),
+            scheduler: parking_lot::Mutex::new(None),
         }
     }
 
-    pub async fn start(&self, pool: SqlitePool) {
-        self.stop().await;
-        self.running_flag.store(true, Ordering::SeqCst);
+    /// Schedules new or changed services and drops deleted ones, then starts
+    /// the scheduler if it is not running. Unchanged services keep their
+    /// schedule, and newly loaded interval checks are spread over their first
+    /// interval so they don't all fire at once. The scheduler is started even
+    /// when the services cannot be loaded, so later changes still get checked.
+    pub async fn start(&'static self, pool: SqlitePool) {
+        match sqlx::query_as::<_, Service>("SELECT * FROM services").fetch_all(&pool).await {
+            Ok(services) => self.replace_schedule(services),
+            Err(e) => tracing::warn!("Failed to load services for the collectors, keeping the current schedule: {}", e),
+        }
+
+        let mut scheduler = self.scheduler.lock();
+        if scheduler.as_ref().is_none_or(|handle| handle.is_finished()) {
+            *scheduler = Some(tokio::spawn(self.run_scheduler(pool)));
+        }
+        self.wake.notify_one();
+    }
+
+    fn replace_schedule(&self, services: Vec<Service>) {
+        let mut schedule = self.schedule.lock();
+        let ids: Vec<i64> = services.iter().map(|service| service.id).collect();
+        schedule.retain(|id, scheduled| {
+            let keep = ids.contains(id);
+            if !keep {
+                scheduled.abort();
+                metrics::remove_service(*id);
+                checks::heartbeat::forget(*id);
+            }
+            keep
+        });
+        let now = Utc::now();
+        for service in services {
+            Self::upsert(&mut schedule, service, now, true);
+        }
+    }
 
-        // Load services
-        let services: Vec<Service> = match sqlx::query_as::<_, Service>("SELECT * FROM services")
-            .fetch_all(&pool)
+    /// Brings a single service's schedule in line with the database after it
+    /// was created, updated or deleted. New and changed interval checks run
+    /// right away.
+    pub async fn reconcile_service(&self, pool: &SqlitePool, service_id: i64) {
+        let service = match sqlx::query_as::<_, Service>("SELECT * FROM services WHERE id = ?")
+            .bind(service_id)
+            .fetch_optional(pool)
             .await
         {
-            Ok(s) => s,
-            Err(_) => Vec::new(),
+            Ok(service) => service,
+            Err(e) => {
+                tracing::warn!("Failed to reconcile collector for service {}: {}", service_id, e);
+                return;
+            }
         };
 
-        let mut new_handles = Vec::new();
-        for service in services {
-            let pool_clone = pool.clone();
-            let running = self.running_flag.clone();
-            let handle = tokio::spawn(async move {
-                let interval_secs = if service.healthcheck_duration_seconds <= 0 { 30 } else { service.healthcheck_duration_seconds } as u64;
-                loop {
-                    if !running.load(Ordering::SeqCst) { break; }
-                    let start = std::time::Instant::now();
-                    let status = match perform_check(&service.healthcheck_url).await {
-                        Ok(up) => if up { "UP" } else { "DOWN" },
-                        Err(_) => "DOWN",
-                    };
-                    let elapsed = start.elapsed().as_secs_f64();
-                    let url_label = service.healthcheck_url.clone();
-                    let name_label = service.service_name.clone();
-                    let labels = [&url_label[..], &name_label[..]];
-                    SERVICE_LATENCY_SECONDS.with_label_values(&labels).set(elapsed);
-                    SERVICE_STATUS.with_label_values(&labels).set(if status == "UP" { 1.0 } else { 0.0 });
-                    SERVICE_CHECKS_TOTAL.with_label_values(&[labels[0], labels[1], status]).inc();
-
-                    // Sleep for configured duration or until stop
-                    let mut slept = 0u64;
-                    while slept < interval_secs {
-                        if !running.load(Ordering::SeqCst) { break; }
-                        sleep(Duration::from_secs(1)).await;
-                        slept += 1;
-                    }
-                    if !running.load(Ordering::SeqCst) { break; }
+        let mut schedule = self.schedule.lock();
+        match service {
+            Some(service) => {
+                // Renames should not leave the old series behind until the next check
+                metrics::service_labels(&service);
+                Self::upsert(&mut schedule, service, Utc::now(), false);
+            }
+            None => {
+                if let Some(scheduled) = schedule.remove(&service_id) {
+                    scheduled.abort();
                 }
-                let _ = pool_clone; // keep pool in scope if needed later
-            });
-            new_handles.push(handle);
+                SERVICE_STATES.lock().remove(&service_id);
+                metrics::remove_service(service_id);
+                checks::heartbeat::forget(service_id);
+            }
         }
+        self.wake.notify_one();
+    }
 
-        *self.handles.lock() = new_handles;
+    fn upsert(schedule: &mut HashMap<i64, ScheduledService>, service: Service, now: DateTime<Utc>, spread: bool) {
+        if let Some(scheduled) = schedule.get(&service.id) {
+            if scheduled.service.updated_at == service.updated_at {
+                return;
+            }
+            scheduled.abort();
+        }
+        schedule.insert(service.id, ScheduledService {
+            next_run_at: schedule::first_run(&service, now, spread),
+            service: Arc::new(service),
+            unsaved: true,
+            running: None,
+        });
     }
 
     pub async fn stop(&self) {
-        self.running_flag.store(false, Ordering::SeqCst);
-        let mut handles = Vec::new();
-        std::mem::swap(&mut *self.handles.lock(), &mut handles);
-        for h in handles { let _ = h.abort(); }
+        if let Some(scheduler) = self.scheduler.lock().take() {
+            scheduler.abort();
+        }
+        for (_, scheduled) in self.schedule.lock().drain() {
+            scheduled.abort();
+        }
     }
 
-    pub async fn restart(&self, pool: SqlitePool) {
+    /// Restarts every collector, resetting all schedules.
+    pub async fn restart(&'static self, pool: SqlitePool) {
+        self.stop().await;
         self.start(pool).await;
     }
+
+    /// Starts due checks and sleeps until the next one is due or the
+    /// schedule changes. A check still running when it is due again is
+    /// skipped for that run.
+    async fn run_scheduler(&'static self, pool: SqlitePool) {
+        loop {
+            let (next_wake, unsaved) = {
+                let mut schedule = self.schedule.lock();
+                let now = Utc::now();
+                let mut unsaved = Vec::new();
+                for (id, scheduled) in schedule.iter_mut() {
+                    if let Some(due) = scheduled.next_run_at.filter(|due| *due <= now) {
+                        if scheduled.running.as_ref().is_none_or(|running| running.is_finished()) {
+                            let pool = pool.clone();
+                            let service = scheduled.service.clone();
+                            scheduled.running = Some(tokio::spawn(async move {
+                                run_check(&pool, &service).await;
+                            }));
+                        } else {
+                            tracing::debug!("Skipping check of service {}, the previous one is still running", id);
+                        }
+                        scheduled.next_run_at = schedule::next_run(&scheduled.service, due, now);
+                        scheduled.unsaved = true;
+                    }
+                    if scheduled.unsaved {
+                        scheduled.unsaved = false;
+                        unsaved.push((*id, scheduled.next_run_at));
+                    }
+                }
+                (schedule.values().filter_map(|scheduled| scheduled.next_run_at).min(), unsaved)
+            };
+
+            for (service_id, next_run_at) in unsaved {
+                let _ = sqlx::query("UPDATE services SET next_run_at = ? WHERE id = ?")
+                    .bind(next_run_at)
+                    .bind(service_id)
+                    .execute(&pool)
+                    .await;
+            }
+
+            let wait = match next_wake {
+                Some(at) => (at - Utc::now()).to_std().unwrap_or(Duration::ZERO),
+                None => Duration::from_secs(3600),
+            };
+            tokio::select! {
+                _ = sleep(wait) => {}
+                _ = self.wake.notified() => {}
+            }
+        }
+    }
 }
 
-async fn perform_check(url: &str) -> Result<bool, reqwest::Error> {
-    let client = reqwest::Client::new();
-    let resp = client
-        .get(url)
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await?;
-    Ok(resp.status().is_success())
+/// Result of a single health check against a service.
+#[derive(Debug, Clone)]
+pub struct CheckOutcome {
+    /// What this check observed.
+    pub status: String,
+    /// The service's state after applying failure/recovery thresholds.
+    pub service_state: String,
+    /// How many attempts the check took, including retries.
+    pub attempts: u32,
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+    pub latency_seconds: f64,
+    pub http_status_code: Option<u16>,
+    pub error: Option<String>,
+    pub tls_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub steps: Option<Vec<checks::transaction::StepResult>>,
+    pub timings: Option<checks::PhaseTimings>,
 }
 
+impl CheckOutcome {
+    /// WARNING checks still succeeded, so they count as up.
+    pub fn is_up(&self) -> bool {
+        self.status == "UP" || self.status == "WARNING"
+    }
+}
+
+/// Checks a service, exports the result to Prometheus and stores it in the
+/// `check_results` history table.
+pub async fn run_check(pool: &SqlitePool, service: &Service) -> CheckOutcome {
+    let mut outcome = perform_check(service).await;
+    let in_maintenance = maintenance::active_window(pool, service, outcome.checked_at).await.is_some();
+
+    // The thresholded state is frozen during maintenance, so the first check
+    // after the window is compared with the state before it
+    let previous = tracked_state(pool, service.id).await;
+    if in_maintenance {
+        outcome.service_state = "MAINTENANCE".to_string();
+    } else {
+        let current = next_state(service, previous.as_ref(), &outcome.status);
+        outcome.service_state = current.state.clone();
+        SERVICE_STATES.lock().insert(service.id, current);
+    }
+
+    let [id, url, name] = metrics::service_labels(service);
+    let labels = [&id[..], &url, &name];
+    SERVICE_LATENCY_SECONDS.with_label_values(&labels).set(outcome.latency_seconds);
+    SERVICE_LATENCY_HISTOGRAM.with_label_values(&labels).observe(outcome.latency_seconds);
+    if let Some(timings) = &outcome.timings {
+        for (phase, ms) in timings.phases() {
+            if let Some(ms) = ms {
+                SERVICE_PHASE_HISTOGRAM.with_label_values(&[&id, &url, &name, phase]).observe(ms / 1000.0);
+            }
+        }
+    }
+    if !in_maintenance {
+        SERVICE_STATUS.with_label_values(&labels).set(if outcome.service_state == "DOWN" { 0.0 } else { 1.0 });
+    }
+    SERVICE_IN_MAINTENANCE.with_label_values(&labels).set(if in_maintenance { 1.0 } else { 0.0 });
+    SERVICE_CHECKS_TOTAL.with_label_values(&[&id, &url, &name, &outcome.status]).inc();
+    if let Some(expires_at) = outcome.tls_expires_at {
+        let days = (expires_at - outcome.checked_at).num_seconds() as f64 / 86_400.0;
+        SERVICE_TLS_CERT_EXPIRY_DAYS.with_label_values(&labels).set(days);
+    }
+
+    if let Err(e) = record_check_result(pool, service.id, &outcome).await {
+        tracing::warn!("Failed to store check result for service {}: {}", service.id, e);
+    }
+
+    if let Some(previous) = previous.filter(|_| !in_maintenance) {
+        if previous.state != outcome.service_state {
+            notifications::notify(pool, &StateChange {
+                service_id: service.id,
+                service_name: service.service_name.clone(),
+                healthcheck_url: service.healthcheck_url.clone(),
+                old_state: previous.state,
+                new_state: outcome.service_state.clone(),
+                latency_ms: outcome.latency_seconds * 1000.0,
+                error: outcome.error.clone(),
+                timestamp: outcome.checked_at,
+            }).await;
+        }
+    }
+
+    outcome
+}
 
+/// Confirmed state of a service plus the streaks used to change it.
+#[derive(Debug, Clone)]
+struct TrackedState {
+    state: String,
+    consecutive_failures: i64,
+    consecutive_successes: i64,
+}
+
+/// Tracked state per service, used to suppress flapping and detect transitions.
+static SERVICE_STATES: Lazy<parking_lot::Mutex<HashMap<i64, TrackedState>>> = Lazy::new(|| parking_lot::Mutex::new(HashMap::new()));
+
+/// Returns the state a service had before the current check, falling back to
+/// the stored history so transitions across restarts are still noticed.
+async fn tracked_state(pool: &SqlitePool, service_id: i64) -> Option<TrackedState> {
+    if let Some(state) = SERVICE_STATES.lock().get(&service_id) {
+        return Some(state.clone());
+    }
+
+    sqlx::query_scalar::<_, String>(
+        "SELECT COALESCE(service_state, status) FROM check_results
+         WHERE service_id = ? AND COALESCE(service_state, '') != 'MAINTENANCE'
+         ORDER BY checked_at DESC LIMIT 1"
+    )
+    .bind(service_id)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+    .map(|state| TrackedState { state, consecutive_failures: 0, consecutive_successes: 0 })
+}
+
+/// Moves to DOWN only after `failure_threshold` consecutive failures and back
+/// to UP after `recovery_threshold` consecutive successes. A service that is
+/// up follows WARNING checks immediately. A service without any previous
+/// state takes the result of its first check directly.
+fn next_state(service: &Service, previous: Option<&TrackedState>, status: &str) -> TrackedState {
+    let up = status != "DOWN";
+    let Some(previous) = previous else {
+        return TrackedState {
+            state: status.to_string(),
+            consecutive_failures: if up { 0 } else { 1 },
+            consecutive_successes: if up { 1 } else { 0 },
+        };
+    };
+
+    let mut next = previous.clone();
+    if up {
+        next.consecutive_successes += 1;
+        next.consecutive_failures = 0;
+        if next.state != "DOWN" || next.consecutive_successes >= service.recovery_threshold.max(1) {
+            next.state = status.to_string();
+        }
+    } else {
+        next.consecutive_failures += 1;
+        next.consecutive_successes = 0;
+        if next.state != "DOWN" && next.consecutive_failures >= service.failure_threshold.max(1) {
+            next.state = "DOWN".to_string();
+        }
+    }
+    next
+}
+
+async fn record_check_result(pool: &SqlitePool, service_id: i64, outcome: &CheckOutcome) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO check_results (service_id, checked_at, status, service_state, attempts, latency_ms, http_status_code, error, tls_expires_at, steps, timings) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(service_id)
+    .bind(outcome.checked_at)
+    .bind(&outcome.status)
+    .bind(&outcome.service_state)
+    .bind(outcome.attempts as i64)
+    .bind(outcome.latency_seconds * 1000.0)
+    .bind(outcome.http_status_code.map(i64::from))
+    .bind(&outcome.error)
+    .bind(outcome.tls_expires_at)
+    .bind(outcome.steps.as_ref().map(sqlx::types::Json))
+    .bind(outcome.timings.as_ref().map(sqlx::types::Json))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Bounds how many checks are in flight at once, across the collectors and
+/// the API.
+static CHECK_PERMITS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(CONFIG.max_concurrent_checks.max(1)));
+
+/// Runs the check, retrying failed attempts with exponential backoff. The
+/// reported latency is that of the final attempt.
+async fn perform_check(service: &Service) -> CheckOutcome {
+    let timeout = Duration::from_secs(
+        service.timeout_seconds.map(|t| t.max(1) as u64).unwrap_or(CONFIG.health_check_timeout_seconds)
+    );
+    let retries = match service.check_type.as_str() {
+        // Retrying cannot make a missed heartbeat arrive
+        "heartbeat" => 0,
+        _ => service.retries.map(|r| r.max(0) as u32).unwrap_or(CONFIG.health_check_retries),
+    };
+    let backoff_ms = service.retry_backoff_ms.map(|b| b.max(0) as u64).unwrap_or(CONFIG.health_check_retry_backoff_ms);
+
+    let mut attempts = 0;
+    loop {
+        if attempts > 0 {
+            sleep(Duration::from_millis(backoff_ms.saturating_mul(2u64.saturating_pow(attempts - 1)))).await;
+        }
+        attempts += 1;
+
+        // Only held while probing, not during the backoff
+        let permit = CHECK_PERMITS.acquire().await.expect("check semaphore is never closed");
+        let checked_at = chrono::Utc::now();
+        let attempt = checks::attempt(service, timeout).await;
+        drop(permit);
+        let outcome = CheckOutcome {
+            status: attempt.status.to_string(),
+            service_state: String::new(),
+            attempts,
+            checked_at,
+            latency_seconds: attempt.latency_seconds,
+            http_status_code: attempt.http_status_code,
+            error: attempt.error,
+            tls_expires_at: attempt.tls_expires_at,
+            steps: attempt.steps,
+            timings: attempt.timings,
+        };
+        if outcome.is_up() || attempts > retries {
+            return outcome;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use super::*;
+
+    fn service(failure_threshold: i64, recovery_threshold: i64) -> Service {
+        let now = Utc::now();
+        Service {
+            id: 1,
+            service_name: "api".to_string(),
+            healthcheck_url: "http://127.0.0.1/health".to_string(),
+            healthcheck_duration_seconds: 30,
+            tags: sqlx::types::Json(Vec::new()),
+            cron_expression: None,
+            next_run_at: None,
+            check_type: "http".to_string(),
+            check_config: sqlx::types::Json(serde_json::json!({})),
+            heartbeat_token: None,
+            last_heartbeat_at: None,
+            slo_target: 99.9,
+            failure_threshold,
+            recovery_threshold,
+            timeout_seconds: None,
+            retries: None,
+            retry_backoff_ms: None,
+            tls_expiry_warning_days: None,
+            assertions: sqlx::types::Json(Vec::new()),
+            http_method: "GET".to_string(),
+            request_headers: sqlx::types::Json(BTreeMap::new()),
+            request_body: None,
+            expected_status_codes: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn run(service: &Service, start: Option<TrackedState>, statuses: &[&str]) -> Vec<String> {
+        let mut state = start;
+        statuses
+            .iter()
+            .map(|status| {
+                let next = next_state(service, state.as_ref(), status);
+                let name = next.state.clone();
+                state = Some(next);
+                name
+            })
+            .collect()
+    }
+
+    fn tracked(state: &str) -> Option<TrackedState> {
+        Some(TrackedState { state: state.to_string(), consecutive_failures: 0, consecutive_successes: 0 })
+    }
+
+    #[test]
+    fn first_check_is_taken_directly() {
+        assert_eq!(run(&service(3, 3), None, &["DOWN"]), ["DOWN"]);
+        assert_eq!(run(&service(3, 3), None, &["WARNING"]), ["WARNING"]);
+    }
+
+    #[test]
+    fn goes_down_after_failure_threshold() {
+        let states = run(&service(3, 1), tracked("UP"), &["DOWN", "DOWN", "UP", "DOWN", "DOWN", "DOWN"]);
+        assert_eq!(states, ["UP", "UP", "UP", "UP", "UP", "DOWN"]);
+    }
+
+    #[test]
+    fn recovers_after_recovery_threshold() {
+        let states = run(&service(1, 2), tracked("DOWN"), &["UP", "DOWN", "UP", "UP"]);
+        assert_eq!(states, ["DOWN", "DOWN", "DOWN", "UP"]);
+    }
+
+    #[test]
+    fn thresholds_below_one_act_as_one() {
+        assert_eq!(run(&service(0, 0), tracked("UP"), &["DOWN", "UP"]), ["DOWN", "UP"]);
+    }
+
+    #[test]
+    fn warning_counts_as_up() {
+        assert_eq!(run(&service(2, 1), tracked("UP"), &["WARNING", "UP"]), ["WARNING", "UP"]);
+        assert_eq!(run(&service(1, 2), tracked("DOWN"), &["WARNING", "WARNING"]), ["DOWN", "WARNING"]);
+    }
+
+    #[tokio::test]
+    async fn scheduler_starts_when_services_cannot_be_loaded() {
+        // No tables, so loading the services fails
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let manager: &'static CollectorManager = Box::leak(Box::new(CollectorManager::new()));
+
+        manager.start(pool).await;
+        assert!(manager.scheduler.lock().as_ref().is_some_and(|handle| !handle.is_finished()));
+        manager.stop().await;
+    }
+}
@@ -1,4 +1,7 @@
 use std::env;
+use once_cell::sync::Lazy;
+
+pub static CONFIG: Lazy<Config> = Lazy::new(Config::from_env);
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -9,6 +12,28 @@ pub struct Config {
     pub port: u16,
     pub prometheus_enabled: bool,
     pub rust_log: String,
+    pub health_check_timeout_seconds: u64,
+    pub health_check_retries: u32,
+    pub health_check_retry_backoff_ms: u64,
+    pub tls_expiry_warning_days: i64,
+    pub command_checks_allowed: Vec<String>,
+    pub max_concurrent_checks: usize,
+    pub http_pool_max_idle_per_host: usize,
+    pub http_pool_idle_timeout_seconds: u64,
+    pub latency_histogram_buckets: Vec<f64>,
+    pub cron_jitter_seconds: u64,
+    pub history_retention_days: i64,
+    pub uptime_refresh_seconds: u64,
+    pub alert_webhook_urls: Vec<String>,
+    pub alert_retries: u32,
+    pub alert_retry_backoff_seconds: u64,
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_tls: String,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_from: String,
+    pub alert_email_to: Vec<String>,
 }
 
 impl Config {
@@ -16,7 +41,7 @@ impl Config {
         // Load config.env file if it exists
         dotenv::from_filename("config.env").ok();
 
-        let config = Self {
+        Self {
             database_url: env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "sqlite:health_check.db?mode=rwc".to_string()),
             
@@ -41,9 +66,101 @@ impl Config {
             
             rust_log: env::var("RUST_LOG")
                 .unwrap_or_else(|_| "info".to_string()),
-        };
-        
-        config
+            
+            health_check_timeout_seconds: env::var("HEALTH_CHECK_TIMEOUT")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+            
+            health_check_retries: env::var("HEALTH_CHECK_RETRIES")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .unwrap_or(0),
+            
+            health_check_retry_backoff_ms: env::var("HEALTH_CHECK_RETRY_BACKOFF_MS")
+                .unwrap_or_else(|_| "500".to_string())
+                .parse()
+                .unwrap_or(500),
+            
+            tls_expiry_warning_days: env::var("TLS_EXPIRY_WARNING_DAYS")
+                .unwrap_or_else(|_| "21".to_string())
+                .parse()
+                .unwrap_or(21),
+            
+            command_checks_allowed: split_list(&env::var("COMMAND_CHECKS_ALLOWED").unwrap_or_default()),
+            
+            max_concurrent_checks: env::var("MAX_CONCURRENT_CHECKS")
+                .unwrap_or_else(|_| "50".to_string())
+                .parse()
+                .unwrap_or(50),
+            
+            http_pool_max_idle_per_host: env::var("HTTP_POOL_MAX_IDLE_PER_HOST")
+                .unwrap_or_else(|_| "16".to_string())
+                .parse()
+                .unwrap_or(16),
+            
+            http_pool_idle_timeout_seconds: env::var("HTTP_POOL_IDLE_TIMEOUT_SECONDS")
+                .unwrap_or_else(|_| "90".to_string())
+                .parse()
+                .unwrap_or(90),
+            
+            latency_histogram_buckets: parse_buckets(&env::var("LATENCY_HISTOGRAM_BUCKETS").unwrap_or_default()),
+            
+            cron_jitter_seconds: env::var("CRON_JITTER_SECONDS")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .unwrap_or(10),
+            
+            // 0 keeps check history forever
+            history_retention_days: env::var("HISTORY_RETENTION_DAYS")
+                .unwrap_or_else(|_| "90".to_string())
+                .parse()
+                .unwrap_or(90),
+            
+            uptime_refresh_seconds: env::var("UPTIME_REFRESH_SECONDS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .unwrap_or(60),
+            
+            alert_webhook_urls: split_list(&env::var("ALERT_WEBHOOK_URLS").unwrap_or_default()),
+            
+            alert_retries: env::var("ALERT_RETRIES")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .unwrap_or(3),
+            
+            alert_retry_backoff_seconds: env::var("ALERT_RETRY_BACKOFF_SECONDS")
+                .unwrap_or_else(|_| "2".to_string())
+                .parse()
+                .unwrap_or(2),
+            
+            smtp_host: env::var("SMTP_HOST")
+                .ok()
+                .filter(|host| !host.is_empty()),
+            
+            smtp_port: env::var("SMTP_PORT")
+                .unwrap_or_else(|_| "587".to_string())
+                .parse()
+                .unwrap_or(587),
+            
+            // "starttls", "tls" (implicit TLS) or "none"
+            smtp_tls: env::var("SMTP_TLS")
+                .unwrap_or_else(|_| "starttls".to_string())
+                .to_lowercase(),
+            
+            smtp_username: env::var("SMTP_USERNAME")
+                .ok()
+                .filter(|username| !username.is_empty()),
+            
+            smtp_password: env::var("SMTP_PASSWORD")
+                .ok()
+                .filter(|password| !password.is_empty()),
+            
+            smtp_from: env::var("SMTP_FROM")
+                .unwrap_or_else(|_| "nx-health-checker@localhost".to_string()),
+            
+            alert_email_to: split_list(&env::var("ALERT_EMAIL_TO").unwrap_or_default()),
+        }
     }
 
     pub fn socket_addr(&self) -> std::net::SocketAddr {
@@ -52,3 +169,29 @@ impl Config {
             .unwrap_or_else(|_| "127.0.0.1:3030".parse().unwrap())
     }
 }
+
+/// Parses comma-separated histogram bucket bounds in seconds, falling back
+/// to the Prometheus defaults when none are valid.
+fn parse_buckets(value: &str) -> Vec<f64> {
+    let mut buckets: Vec<f64> = split_list(value)
+        .iter()
+        .filter_map(|bound| bound.parse().ok())
+        .filter(|bound: &f64| bound.is_finite() && *bound > 0.0)
+        .collect();
+    buckets.sort_by(f64::total_cmp);
+    buckets.dedup();
+    if buckets.is_empty() {
+        prometheus::DEFAULT_BUCKETS.to_vec()
+    } else {
+        buckets
+    }
+}
+
+/// Splits a comma-separated setting, dropping blank entries.
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
@@ -10,6 +10,9 @@ pub async fn init_database(database_url: &str, username: &str, password: &str) -
 
     // Create tables directly
     create_tables(&pool).await?;
+
+    // Add columns introduced after the initial schema
+    migrate_tables(&pool).await?;
     
     // Initialize admin user if not exists
     init_admin_user(&pool, username, password).await?;
@@ -58,6 +61,159 @@ async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
         .execute(pool)
         .await?;
 
+    // Create check results history table
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS check_results (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            service_id INTEGER NOT NULL,
+            checked_at DATETIME NOT NULL,
+            status TEXT NOT NULL,
+            service_state TEXT,
+            attempts INTEGER NOT NULL DEFAULT 1,
+            latency_ms REAL NOT NULL,
+            http_status_code INTEGER,
+            error TEXT,
+            tls_expires_at DATETIME,
+            steps TEXT,
+            timings TEXT
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    // Create index for per-service history lookups by time
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_check_results_service_time ON check_results(service_id, checked_at)")
+        .execute(pool)
+        .await?;
+
+    // Create index for pruning old results across all services
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_check_results_checked_at ON check_results(checked_at)")
+        .execute(pool)
+        .await?;
+
+    // Create notification delivery log table
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS notification_deliveries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            service_id INTEGER NOT NULL,
+            channel TEXT NOT NULL,
+            target TEXT NOT NULL,
+            event TEXT NOT NULL,
+            success BOOLEAN NOT NULL,
+            attempts INTEGER NOT NULL,
+            response_status INTEGER,
+            error TEXT,
+            created_at DATETIME NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    // Create notification channels table (service_id NULL = all services)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS notification_channels (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            channel_type TEXT NOT NULL,
+            service_id INTEGER,
+            config TEXT NOT NULL,
+            enabled BOOLEAN NOT NULL DEFAULT 1,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    // Create maintenance windows table (service_id and tag NULL = all services)
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS maintenance_windows (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            service_id INTEGER,
+            tag TEXT,
+            starts_at DATETIME,
+            ends_at DATETIME,
+            cron_expression TEXT,
+            duration_minutes INTEGER,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes check results older than `HISTORY_RETENTION_DAYS` once an hour.
+pub fn spawn_history_pruning(pool: SqlitePool) {
+    let retention_days = crate::config::CONFIG.history_retention_days;
+    if retention_days <= 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            let cutoff = chrono::Utc::now() - chrono::Duration::days(retention_days);
+            match sqlx::query("DELETE FROM check_results WHERE checked_at < ?")
+                .bind(cutoff)
+                .execute(&pool)
+                .await
+            {
+                Ok(result) if result.rows_affected() > 0 => {
+                    info!("Pruned {} check results older than {} days", result.rows_affected(), retention_days);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to prune check history: {}", e),
+            }
+        }
+    });
+}
+
+async fn migrate_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    add_column_if_missing(pool, "services", "slo_target", "REAL NOT NULL DEFAULT 99.9").await?;
+    add_column_if_missing(pool, "notification_deliveries", "channel_id", "INTEGER").await?;
+    add_column_if_missing(pool, "services", "failure_threshold", "INTEGER NOT NULL DEFAULT 1").await?;
+    add_column_if_missing(pool, "services", "recovery_threshold", "INTEGER NOT NULL DEFAULT 1").await?;
+    add_column_if_missing(pool, "services", "timeout_seconds", "INTEGER").await?;
+    add_column_if_missing(pool, "services", "retries", "INTEGER").await?;
+    add_column_if_missing(pool, "services", "retry_backoff_ms", "INTEGER").await?;
+    add_column_if_missing(pool, "services", "assertions", "TEXT NOT NULL DEFAULT '[]'").await?;
+    add_column_if_missing(pool, "services", "http_method", "TEXT NOT NULL DEFAULT 'GET'").await?;
+    add_column_if_missing(pool, "services", "request_headers", "TEXT NOT NULL DEFAULT '{}'").await?;
+    add_column_if_missing(pool, "services", "request_body", "TEXT").await?;
+    add_column_if_missing(pool, "services", "expected_status_codes", "TEXT").await?;
+    add_column_if_missing(pool, "services", "check_type", "TEXT NOT NULL DEFAULT 'http'").await?;
+    add_column_if_missing(pool, "services", "tls_expiry_warning_days", "INTEGER").await?;
+    add_column_if_missing(pool, "services", "check_config", "TEXT NOT NULL DEFAULT '{}'").await?;
+    add_column_if_missing(pool, "services", "heartbeat_token", "TEXT").await?;
+    add_column_if_missing(pool, "services", "last_heartbeat_at", "DATETIME").await?;
+    add_column_if_missing(pool, "services", "cron_expression", "TEXT").await?;
+    add_column_if_missing(pool, "services", "next_run_at", "DATETIME").await?;
+    add_column_if_missing(pool, "services", "tags", "TEXT NOT NULL DEFAULT '[]'").await?;
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_services_heartbeat_token ON services(heartbeat_token)")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+async fn add_column_if_missing(pool: &SqlitePool, table: &str, column: &str, definition: &str) -> Result<(), sqlx::Error> {
+    let exists = sqlx::query(&format!("SELECT COUNT(*) as count FROM pragma_table_info('{}') WHERE name = ?", table))
+        .bind(column)
+        .fetch_one(pool)
+        .await?
+        .get::<i64, _>("count");
+
+    if exists == 0 {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+        info!("Added column {}.{}", table, column);
+    }
+
     Ok(())
 }
 
@@ -74,11 +230,12 @@ async fn init_sample_services(pool: &SqlitePool) -> Result<(), sqlx::Error> {
         
         // Sample service 1: Google
         sqlx::query(
-            "INSERT INTO services (service_name, healthcheck_url, healthcheck_duration_seconds, created_at, updated_at) VALUES (?, ?, ?, ?, ?)"
+            "INSERT INTO services (service_name, healthcheck_url, healthcheck_duration_seconds, slo_target, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)"
         )
         .bind("Google")
         .bind("https://www.google.com")
         .bind(30)
+        .bind(99.9)
         .bind(now)
         .bind(now)
         .execute(pool)
@@ -0,0 +1,102 @@
+use std::time::Duration;
+use lettre::{
+    message::header::ContentType,
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use crate::config::CONFIG;
+use crate::notifications::StateChange;
+
+pub const DEFAULT_SUBJECT_DOWN: &str = "[DOWN] {{service_name}} is not responding";
+pub const DEFAULT_BODY_DOWN: &str = "{{service_name}} changed state from {{old_state}} to {{new_state}} at {{timestamp}}.
+
+URL: {{healthcheck_url}}
+Latency: {{latency}}
+Error: {{error}}
+";
+pub const DEFAULT_SUBJECT_WARNING: &str = "[WARNING] {{service_name}} needs attention";
+pub const DEFAULT_BODY_WARNING: &str = "{{service_name}} changed state from {{old_state}} to {{new_state}} at {{timestamp}}.
+
+URL: {{healthcheck_url}}
+Warning: {{error}}
+";
+pub const DEFAULT_SUBJECT_RECOVERED: &str = "[RECOVERED] {{service_name}} is back UP";
+pub const DEFAULT_BODY_RECOVERED: &str = "{{service_name}} recovered at {{timestamp}} (was {{old_state}}).
+
+URL: {{healthcheck_url}}
+Latency: {{latency}}
+";
+
+/// Fills `{{placeholder}}` values in a subject or body template.
+pub fn render_template(template: &str, change: &StateChange) -> String {
+    template
+        .replace("{{service_id}}", &change.service_id.to_string())
+        .replace("{{service_name}}", &change.service_name)
+        .replace("{{healthcheck_url}}", &change.healthcheck_url)
+        .replace("{{old_state}}", &change.old_state)
+        .replace("{{new_state}}", &change.new_state)
+        .replace("{{latency}}", &format!("{:.0} ms", change.latency_ms))
+        .replace("{{error}}", change.error.as_deref().unwrap_or("none"))
+        .replace("{{timestamp}}", &change.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+}
+
+fn transport() -> Result<AsyncSmtpTransport<Tokio1Executor>, String> {
+    let host = CONFIG.smtp_host.as_deref().ok_or("SMTP_HOST is not configured")?;
+
+    let mut builder = match CONFIG.smtp_tls.as_str() {
+        "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host).map_err(|e| e.to_string())?,
+        "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host).map_err(|e| e.to_string())?,
+    }
+    .port(CONFIG.smtp_port)
+    .timeout(Some(Duration::from_secs(10)));
+
+    if let (Some(username), Some(password)) = (&CONFIG.smtp_username, &CONFIG.smtp_password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    Ok(builder.build())
+}
+
+/// Sends a plain-text email to every recipient through the configured SMTP server.
+pub async fn send(to: &[String], subject: &str, body: &str) -> Result<(), String> {
+    let mut message = Message::builder()
+        .from(CONFIG.smtp_from.parse().map_err(|e| format!("Invalid SMTP_FROM: {}", e))?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for recipient in to {
+        message = message.to(recipient.parse().map_err(|e| format!("Invalid recipient {}: {}", recipient, e))?);
+    }
+    let message = message.body(body.to_string()).map_err(|e| e.to_string())?;
+
+    transport()?
+        .send(message)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use super::*;
+
+    #[test]
+    fn fills_every_placeholder() {
+        let change = StateChange {
+            service_id: 7,
+            service_name: "api".to_string(),
+            healthcheck_url: "https://api.example.com/health".to_string(),
+            old_state: "UP".to_string(),
+            new_state: "DOWN".to_string(),
+            latency_ms: 1234.4,
+            error: None,
+            timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap(),
+        };
+        let template = "{{service_id}} {{service_name}} {{healthcheck_url}} {{old_state}}->{{new_state}} {{latency}} {{error}} {{timestamp}} {{unknown}}";
+        assert_eq!(
+            render_template(template, &change),
+            "7 api https://api.example.com/health UP->DOWN 1234 ms none 2024-05-01 12:30:00 UTC {{unknown}}"
+        );
+    }
+}
@@ -1,14 +1,20 @@
 
 
 use axum::{
-    extract::{State, Form, Path},
+    extract::{State, Form, Path, Query},
     http::{HeaderMap, StatusCode, header},
     response::{Html, Response, Json},
 };
 use sqlx::{SqlitePool, Row};
-use crate::metrics::{SERVICE_STATUS, SERVICE_LATENCY_SECONDS, SERVICE_CHECKS_TOTAL};
+use crate::assertions::Assertion;
+use crate::checks::{self, heartbeat, http::parse_status_codes};
+use crate::collector::{run_check, GLOBAL_COLLECTOR};
+use crate::config::CONFIG;
+use futures_util::StreamExt;
+use crate::{notifications, schedule, uptime};
+use std::collections::BTreeMap;
 use std::fs;
-use crate::{auth::{SessionStore, create_session, require_auth, extract_session_id, get_session}, models::{LoginRequest, LoginResponse, Service, CreateServiceRequest, UpdateServiceRequest, ChangePasswordRequest, ChangePasswordResponse}};
+use crate::{auth::{SessionStore, create_session, require_auth, extract_session_id, get_session}, models::{LoginRequest, LoginResponse, Service, CreateServiceRequest, UpdateServiceRequest, CheckResult, HistoryQuery, NotificationChannel, CreateNotificationChannelRequest, UpdateNotificationChannelRequest, NotificationDelivery, DeliveryQuery, MaintenanceWindow, CreateMaintenanceWindowRequest, UpdateMaintenanceWindowRequest, ChangePasswordRequest, ChangePasswordResponse, REDACTED, restore_redacted}};
 
 pub async fn index(
     State((_pool, sessions)): State<(SqlitePool, SessionStore)>,
@@ -21,8 +27,7 @@ pub async fn index(
             .status(StatusCode::FOUND)
             .header("Location", "/dashboard")
             .body(axum::body::Body::from("Redirecting to dashboard..."))
-            .unwrap()
-            .into());
+            .unwrap());
     }
     
     // User is not authenticated, show index page
@@ -42,8 +47,7 @@ pub async fn login_page(
             .status(StatusCode::FOUND)
             .header("Location", "/dashboard")
             .body(axum::body::Body::from("Redirecting to dashboard..."))
-            .unwrap()
-            .into());
+            .unwrap());
     }
     
     // User is not authenticated, show login page
@@ -78,13 +82,11 @@ pub async fn login(
             };
             
             // Add session cookie
-            let response = Response::builder()
+            Response::builder()
                 .status(StatusCode::OK)
                 .header("Set-Cookie", format!("session_id={}; Path=/; HttpOnly", session_id))
                 .body(axum::body::Body::from(serde_json::to_string(&response_data).unwrap()))
-                .unwrap();
-            
-            response
+                .unwrap()
         }
         Ok(None) => {
             Response::builder()
@@ -126,8 +128,7 @@ pub async fn dashboard(
                 .status(StatusCode::FOUND)
                 .header("Location", "/login")
                 .body(axum::body::Body::from("Redirecting to login..."))
-                .unwrap()
-                .into());
+                .unwrap());
         }
     };
 
@@ -155,8 +156,7 @@ pub async fn change_password_page(
                 .status(StatusCode::FOUND)
                 .header("Location", "/login")
                 .body(axum::body::Body::from("Redirecting to login..."))
-                .unwrap()
-                .into());
+                .unwrap());
         }
     };
 
@@ -201,8 +201,7 @@ pub async fn get_services(
         Err(_) => Err(Response::builder()
             .status(StatusCode::INTERNAL_SERVER_ERROR)
             .body(axum::body::Body::from("Failed to fetch services"))
-            .unwrap()
-            .into()),
+            .unwrap()),
     }
 }
 
@@ -211,110 +210,245 @@ pub async fn create_service(
     Json(service_data): Json<CreateServiceRequest>,
 ) -> Result<Json<serde_json::Value>, Response> {
     let now = chrono::Utc::now();
+    let check_type = service_data.check_type.as_deref().unwrap_or("http").to_lowercase();
+    let check_config = service_data.check_config.clone().unwrap_or_else(|| serde_json::json!({}));
+    checks::validate(&check_type, &service_data.healthcheck_url, &check_config).map_err(bad_request)?;
+    let slo_target = service_data.slo_target.unwrap_or(99.9);
+    if !valid_slo_target(slo_target) {
+        return Err(invalid_slo_target_response());
+    }
+    let failure_threshold = service_data.failure_threshold.unwrap_or(1);
+    let recovery_threshold = service_data.recovery_threshold.unwrap_or(1);
+    if failure_threshold < 1 || recovery_threshold < 1 {
+        return Err(invalid_threshold_response());
+    }
+    check_non_negative(&[
+        ("timeout_seconds", service_data.timeout_seconds),
+        ("retries", service_data.retries),
+        ("retry_backoff_ms", service_data.retry_backoff_ms),
+        ("tls_expiry_warning_days", service_data.tls_expiry_warning_days),
+    ]).map_err(bad_request)?;
+    validate_assertions(&service_data.assertions).map_err(bad_request)?;
+    let http_method = service_data.http_method.as_deref().unwrap_or("GET").to_uppercase();
+    validate_http_request(Some(&http_method), Some(&service_data.request_headers), service_data.expected_status_codes.as_deref())
+        .map_err(bad_request)?;
+    if let Some(expression) = &service_data.cron_expression {
+        schedule::parse_cron(expression).map_err(bad_request)?;
+    }
+    let heartbeat_token = (check_type == "heartbeat").then(heartbeat::new_token);
     
-    // First insert the service
     match sqlx::query(
-        "INSERT INTO services (service_name, healthcheck_url, healthcheck_duration_seconds, created_at, updated_at) VALUES (?, ?, ?, ?, ?)"
+        "INSERT INTO services (service_name, healthcheck_url, healthcheck_duration_seconds, tags, cron_expression, check_type, check_config, heartbeat_token, slo_target, failure_threshold, recovery_threshold, timeout_seconds, retries, retry_backoff_ms, tls_expiry_warning_days, assertions, http_method, request_headers, request_body, expected_status_codes, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
     )
     .bind(&service_data.service_name)
     .bind(&service_data.healthcheck_url)
     .bind(service_data.healthcheck_duration_seconds)
+    .bind(sqlx::types::Json(&service_data.tags))
+    .bind(&service_data.cron_expression)
+    .bind(&check_type)
+    .bind(sqlx::types::Json(&check_config))
+    .bind(&heartbeat_token)
+    .bind(slo_target)
+    .bind(failure_threshold)
+    .bind(recovery_threshold)
+    .bind(service_data.timeout_seconds)
+    .bind(service_data.retries)
+    .bind(service_data.retry_backoff_ms)
+    .bind(service_data.tls_expiry_warning_days)
+    .bind(sqlx::types::Json(&service_data.assertions))
+    .bind(&http_method)
+    .bind(sqlx::types::Json(&service_data.request_headers))
+    .bind(&service_data.request_body)
+    .bind(&service_data.expected_status_codes)
     .bind(now)
     .bind(now)
     .execute(&pool)
     .await
     {
-        Ok(_) => {
-            // Then get the last inserted ID
-            match sqlx::query("SELECT last_insert_rowid() as id")
-                .fetch_one(&pool)
-                .await
-            {
-                Ok(row) => {
-                    let service_id: i64 = row.get("id");
-                    Ok(Json(serde_json::json!({
-                        "success": true,
-                        "message": "Service created successfully",
-                        "id": service_id
-                    })))
-                },
-                Err(_) => Err(Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(axum::body::Body::from("Failed to get service ID"))
-                    .unwrap()
-                    .into()),
-            }
-        },
+        // The ID must come from the insert itself, another pooled connection
+        // would not see it
+        Ok(result) => {
+            let service_id = result.last_insert_rowid();
+            GLOBAL_COLLECTOR.reconcile_service(&pool, service_id).await;
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "message": "Service created successfully",
+                "id": service_id,
+                "heartbeat_url": heartbeat_token.map(|token| format!("/api/heartbeat/{}", token))
+            })))
+        }
         Err(_) => Err(Response::builder()
             .status(StatusCode::INTERNAL_SERVER_ERROR)
             .body(axum::body::Body::from("Failed to create service"))
-            .unwrap()
-            .into()),
+            .unwrap()),
     }
 }
 
 pub async fn update_service(
     State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
     Path(service_id): Path<i64>,
-    Json(service_data): Json<UpdateServiceRequest>,
+    Json(mut service_data): Json<UpdateServiceRequest>,
 ) -> Result<Json<serde_json::Value>, Response> {
-    let now = chrono::Utc::now();
-    
-    // Update each field individually if provided
-    if let Some(name) = &service_data.service_name {
-        if let Err(_) = sqlx::query("UPDATE services SET service_name = ?, updated_at = ? WHERE id = ?")
-            .bind(name)
-            .bind(now)
-            .bind(service_id)
-            .execute(&pool)
-            .await
-        {
-            return Err(Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(axum::body::Body::from("Failed to update service name"))
-                .unwrap()
-                .into());
+    // Validate everything before touching the row
+    let check_type = service_data.check_type.as_deref().map(str::to_lowercase);
+    let mut needs_heartbeat_token = false;
+    if check_type.is_some() || service_data.healthcheck_url.is_some() || service_data.check_config.is_some() || service_data.request_headers.is_some() {
+        let current = fetch_service(&pool, service_id).await?;
+        // Secrets read from GET /api/services come back redacted
+        if let Some(check_config) = &mut service_data.check_config {
+            restore_redacted(check_config, &current.check_config);
         }
-    }
-    
-    if let Some(url) = &service_data.healthcheck_url {
-        if let Err(_) = sqlx::query("UPDATE services SET healthcheck_url = ?, updated_at = ? WHERE id = ?")
-            .bind(url)
-            .bind(now)
-            .bind(service_id)
-            .execute(&pool)
-            .await
-        {
-            return Err(Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(axum::body::Body::from("Failed to update service URL"))
-                .unwrap()
-                .into());
+        if let Some(headers) = &mut service_data.request_headers {
+            for (name, value) in headers.iter_mut().filter(|(_, value)| *value == REDACTED) {
+                if let Some(stored) = current.request_headers.get(name) {
+                    *value = stored.clone();
+                }
+            }
+        }
+        needs_heartbeat_token = check_type.as_deref() == Some("heartbeat") && current.heartbeat_token.is_none();
+        if check_type.is_some() || service_data.healthcheck_url.is_some() || service_data.check_config.is_some() {
+            checks::validate(
+                check_type.as_deref().unwrap_or(&current.check_type),
+                service_data.healthcheck_url.as_deref().unwrap_or(&current.healthcheck_url),
+                service_data.check_config.as_ref().unwrap_or(&current.check_config),
+            ).map_err(bad_request)?;
         }
     }
-    
+    if service_data.slo_target.is_some_and(|slo| !valid_slo_target(slo)) {
+        return Err(invalid_slo_target_response());
+    }
+    if service_data.failure_threshold.is_some_and(|t| t < 1) || service_data.recovery_threshold.is_some_and(|t| t < 1) {
+        return Err(invalid_threshold_response());
+    }
+    check_non_negative(&[
+        ("timeout_seconds", service_data.timeout_seconds.flatten()),
+        ("retries", service_data.retries.flatten()),
+        ("retry_backoff_ms", service_data.retry_backoff_ms.flatten()),
+        ("tls_expiry_warning_days", service_data.tls_expiry_warning_days.flatten()),
+    ]).map_err(bad_request)?;
+    if let Some(assertions) = &service_data.assertions {
+        validate_assertions(assertions).map_err(bad_request)?;
+    }
+    if let Some(Some(expression)) = &service_data.cron_expression {
+        schedule::parse_cron(expression).map_err(bad_request)?;
+    }
+    let http_method = service_data.http_method.as_deref().map(str::to_uppercase);
+    validate_http_request(
+        http_method.as_deref(),
+        service_data.request_headers.as_ref(),
+        service_data.expected_status_codes.as_ref().and_then(|codes| codes.as_deref()),
+    ).map_err(bad_request)?;
+
+    // Update each field individually if provided
+    if let Some(name) = service_data.service_name {
+        update_service_field(&pool, service_id, "service_name", name).await?;
+    }
+    if let Some(url) = service_data.healthcheck_url {
+        update_service_field(&pool, service_id, "healthcheck_url", url).await?;
+    }
     if let Some(duration) = service_data.healthcheck_duration_seconds {
-        if let Err(_) = sqlx::query("UPDATE services SET healthcheck_duration_seconds = ?, updated_at = ? WHERE id = ?")
-            .bind(duration)
-            .bind(now)
-            .bind(service_id)
-            .execute(&pool)
-            .await
-        {
-            return Err(Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(axum::body::Body::from("Failed to update service duration"))
-                .unwrap()
-                .into());
-        }
+        update_service_field(&pool, service_id, "healthcheck_duration_seconds", duration).await?;
+    }
+    if let Some(tags) = service_data.tags {
+        update_service_field(&pool, service_id, "tags", sqlx::types::Json(tags)).await?;
+    }
+    if let Some(expression) = service_data.cron_expression {
+        update_service_field(&pool, service_id, "cron_expression", expression).await?;
+    }
+    if let Some(check_type) = check_type {
+        update_service_field(&pool, service_id, "check_type", check_type).await?;
+    }
+    let heartbeat_token = needs_heartbeat_token.then(heartbeat::new_token);
+    if let Some(token) = &heartbeat_token {
+        update_service_field(&pool, service_id, "heartbeat_token", token.clone()).await?;
+    }
+    if let Some(check_config) = service_data.check_config {
+        update_service_field(&pool, service_id, "check_config", sqlx::types::Json(check_config)).await?;
+    }
+    if let Some(slo_target) = service_data.slo_target {
+        update_service_field(&pool, service_id, "slo_target", slo_target).await?;
     }
+    if let Some(threshold) = service_data.failure_threshold {
+        update_service_field(&pool, service_id, "failure_threshold", threshold).await?;
+    }
+    if let Some(threshold) = service_data.recovery_threshold {
+        update_service_field(&pool, service_id, "recovery_threshold", threshold).await?;
+    }
+    // `null` clears an override so the global default applies again
+    if let Some(timeout) = service_data.timeout_seconds {
+        update_service_field(&pool, service_id, "timeout_seconds", timeout).await?;
+    }
+    if let Some(retries) = service_data.retries {
+        update_service_field(&pool, service_id, "retries", retries).await?;
+    }
+    if let Some(backoff) = service_data.retry_backoff_ms {
+        update_service_field(&pool, service_id, "retry_backoff_ms", backoff).await?;
+    }
+    if let Some(days) = service_data.tls_expiry_warning_days {
+        update_service_field(&pool, service_id, "tls_expiry_warning_days", days).await?;
+    }
+    if let Some(assertions) = service_data.assertions {
+        update_service_field(&pool, service_id, "assertions", sqlx::types::Json(assertions)).await?;
+    }
+    if let Some(method) = http_method {
+        update_service_field(&pool, service_id, "http_method", method).await?;
+    }
+    if let Some(headers) = service_data.request_headers {
+        update_service_field(&pool, service_id, "request_headers", sqlx::types::Json(headers)).await?;
+    }
+    if let Some(body) = service_data.request_body {
+        update_service_field(&pool, service_id, "request_body", body).await?;
+    }
+    if let Some(codes) = service_data.expected_status_codes {
+        update_service_field(&pool, service_id, "expected_status_codes", codes).await?;
+    }
+    GLOBAL_COLLECTOR.reconcile_service(&pool, service_id).await;
     
     Ok(Json(serde_json::json!({
         "success": true,
-        "message": "Service updated successfully"
+        "message": "Service updated successfully",
+        "heartbeat_url": heartbeat_token.map(|token| format!("/api/heartbeat/{}", token))
     })))
 }
 
+async fn fetch_service(pool: &SqlitePool, service_id: i64) -> Result<Service, Response> {
+    match sqlx::query_as::<_, Service>("SELECT * FROM services WHERE id = ?")
+        .bind(service_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(service)) => Ok(service),
+        Ok(None) => Err(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(axum::body::Body::from("Service not found"))
+            .unwrap()),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Database error"))
+            .unwrap()),
+    }
+}
+
+async fn update_service_field<T>(pool: &SqlitePool, service_id: i64, column: &str, value: T) -> Result<(), Response>
+where
+    T: 'static + Send + for<'q> sqlx::Encode<'q, sqlx::Sqlite> + sqlx::Type<sqlx::Sqlite>,
+{
+    let sql = format!("UPDATE services SET {} = ?, updated_at = ? WHERE id = ?", column);
+    match sqlx::query(&sql)
+        .bind(value)
+        .bind(chrono::Utc::now())
+        .bind(service_id)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from(format!("Failed to update service {}", column.replace('_', " "))))
+            .unwrap()),
+    }
+}
+
 pub async fn delete_service(
     State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
     Path(service_id): Path<i64>,
@@ -324,129 +458,639 @@ pub async fn delete_service(
         .execute(&pool)
         .await
     {
-        Ok(_) => Ok(Json(serde_json::json!({
-            "success": true,
-            "message": "Service deleted successfully"
-        }))),
+        Ok(_) => {
+            // Drop the service's check history, its own channels and maintenance windows along with it
+            let _ = sqlx::query("DELETE FROM check_results WHERE service_id = ?")
+                .bind(service_id)
+                .execute(&pool)
+                .await;
+            let _ = sqlx::query("DELETE FROM notification_channels WHERE service_id = ?")
+                .bind(service_id)
+                .execute(&pool)
+                .await;
+            let _ = sqlx::query("DELETE FROM maintenance_windows WHERE service_id = ?")
+                .bind(service_id)
+                .execute(&pool)
+                .await;
+            GLOBAL_COLLECTOR.reconcile_service(&pool, service_id).await;
+
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "message": "Service deleted successfully"
+            })))
+        }
         Err(_) => Err(Response::builder()
             .status(StatusCode::INTERNAL_SERVER_ERROR)
             .body(axum::body::Body::from("Failed to delete service"))
-            .unwrap()
-            .into()),
+            .unwrap()),
     }
 }
 
+fn valid_slo_target(slo_target: f64) -> bool {
+    slo_target > 0.0 && slo_target <= 100.0
+}
+
+fn invalid_slo_target_response() -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(axum::body::Body::from("SLO target must be greater than 0 and at most 100"))
+        .unwrap()
+}
+
+fn validate_assertions(assertions: &[Assertion]) -> Result<(), String> {
+    assertions.iter().try_for_each(Assertion::validate)
+}
+
+fn validate_http_request(method: Option<&str>, headers: Option<&BTreeMap<String, String>>, status_codes: Option<&str>) -> Result<(), String> {
+    if let Some(method) = method {
+        reqwest::Method::from_bytes(method.as_bytes()).map_err(|_| format!("Invalid HTTP method {}", method))?;
+    }
+    for (name, value) in headers.into_iter().flatten() {
+        reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name {}", name))?;
+        reqwest::header::HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header {}", name))?;
+    }
+    if let Some(codes) = status_codes {
+        parse_status_codes(codes)?;
+    }
+    Ok(())
+}
+
+fn check_non_negative(fields: &[(&str, Option<i64>)]) -> Result<(), String> {
+    for (field, value) in fields {
+        if value.is_some_and(|v| v < 0) {
+            return Err(format!("{} must not be negative", field));
+        }
+    }
+    Ok(())
+}
+
+fn bad_request(message: String) -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(axum::body::Body::from(message))
+        .unwrap()
+}
+
+fn invalid_threshold_response() -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(axum::body::Body::from("Failure and recovery thresholds must be at least 1"))
+        .unwrap()
+}
+
 // Health check function for a single service
 pub async fn check_service_health(
     State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
     Path(service_id): Path<i64>,
 ) -> Result<Json<serde_json::Value>, Response> {
     // Get service details from database
-    let service = match sqlx::query_as::<_, Service>("SELECT * FROM services WHERE id = ?")
+    let service = fetch_service(&pool, service_id).await?;
+
+    // Perform health check, record metrics and store the result
+    let outcome = run_check(&pool, &service).await;
+
+    Ok(Json(serde_json::json!({
+        "service_id": service_id,
+        "service_name": service.service_name,
+        "status": outcome.status,
+        "service_state": outcome.service_state,
+        "attempts": outcome.attempts,
+        "latency_ms": outcome.latency_seconds * 1000.0,
+        "http_status_code": outcome.http_status_code,
+        "error": outcome.error,
+        "tls_expires_at": outcome.tls_expires_at,
+        "steps": outcome.steps,
+        "timings": outcome.timings,
+        "checked_at": outcome.checked_at
+    })))
+}
+
+// Health check function for all services
+pub async fn check_all_services_health(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+) -> Result<Json<serde_json::Value>, Response> {
+    // Get all services
+    let services = match sqlx::query_as::<_, Service>("SELECT * FROM services ORDER BY service_name")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(services) => services,
+        Err(_) => {
+            return Err(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(axum::body::Body::from("Failed to fetch services"))
+                .unwrap());
+        }
+    };
+
+    // The collector's semaphore bounds the probes, this bounds the database work around them
+    let results: Vec<serde_json::Value> = futures_util::stream::iter(services)
+        .map(|service| {
+            let pool = pool.clone();
+            async move {
+                let outcome = run_check(&pool, &service).await;
+                serde_json::json!({
+                    "service_id": service.id,
+                    "service_name": service.service_name,
+                    "status": outcome.status,
+                    "service_state": outcome.service_state,
+                    "attempts": outcome.attempts,
+                    "latency_ms": outcome.latency_seconds * 1000.0,
+                    "http_status_code": outcome.http_status_code,
+                    "error": outcome.error,
+                    "tls_expires_at": outcome.tls_expires_at,
+                    "steps": outcome.steps,
+                    "timings": outcome.timings,
+                    "checked_at": outcome.checked_at
+                })
+            }
+        })
+        .buffered(CONFIG.max_concurrent_checks.max(1))
+        .collect()
+        .await;
+
+    Ok(Json(serde_json::json!({
+        "services": results,
+        "total_checked": results.len()
+    })))
+}
+
+// Check history for a single service, newest first
+pub async fn get_service_history(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Path(service_id): Path<i64>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<serde_json::Value>, Response> {
+    match sqlx::query("SELECT id FROM services WHERE id = ?")
         .bind(service_id)
         .fetch_optional(&pool)
         .await
     {
-        Ok(Some(service)) => service,
+        Ok(Some(_)) => {}
         Ok(None) => {
             return Err(Response::builder()
                 .status(StatusCode::NOT_FOUND)
                 .body(axum::body::Body::from("Service not found"))
-                .unwrap()
-                .into());
+                .unwrap());
         }
         Err(_) => {
             return Err(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
                 .body(axum::body::Body::from("Database error"))
-                .unwrap()
-                .into());
+                .unwrap());
         }
-    };
+    }
+
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
 
-    // Perform health check and record metrics
-    let start = std::time::Instant::now();
-    let status = match perform_health_check(&service.healthcheck_url).await {
-        Ok(status) => status,
-        Err(_) => "DOWN".to_string(),
+    match sqlx::query_as::<_, CheckResult>(
+        "SELECT * FROM check_results
+         WHERE service_id = ?1
+           AND (?2 IS NULL OR checked_at >= ?2)
+           AND (?3 IS NULL OR checked_at <= ?3)
+         ORDER BY checked_at DESC
+         LIMIT ?4"
+    )
+    .bind(service_id)
+    .bind(query.from)
+    .bind(query.to)
+    .bind(limit)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(results) => Ok(Json(serde_json::json!({
+            "service_id": service_id,
+            "results": results,
+            "count": results.len()
+        }))),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to fetch check history"))
+            .unwrap()),
+    }
+}
+
+// Records a heartbeat from a push-based monitor. The token is the only
+// credential, so this route needs no session.
+pub async fn receive_heartbeat(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Path(token): Path<String>,
+) -> Result<Json<serde_json::Value>, Response> {
+    let service = match sqlx::query_as::<_, Service>("SELECT * FROM services WHERE heartbeat_token = ? AND check_type = 'heartbeat'")
+        .bind(&token)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(service)) => service,
+        Ok(None) => {
+            return Err(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(axum::body::Body::from("Unknown heartbeat token"))
+                .unwrap());
+        }
+        Err(_) => {
+            return Err(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(axum::body::Body::from("Database error"))
+                .unwrap());
+        }
     };
-    let elapsed = start.elapsed().as_secs_f64();
-    let url_label = service.healthcheck_url.clone();
-    let name_label = service.service_name.clone();
-    let labels = [&url_label[..], &name_label[..]];
-    SERVICE_LATENCY_SECONDS.with_label_values(&labels).set(elapsed);
-    SERVICE_STATUS.with_label_values(&labels).set(if status == "UP" { 1.0 } else { 0.0 });
-    SERVICE_CHECKS_TOTAL.with_label_values(&[labels[0], labels[1], if status == "UP" { "UP" } else { "DOWN" }]).inc();
+
+    let now = chrono::Utc::now();
+    heartbeat::record(service.id, now);
+    if sqlx::query("UPDATE services SET last_heartbeat_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(service.id)
+        .execute(&pool)
+        .await
+        .is_err()
+    {
+        return Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to record heartbeat"))
+            .unwrap());
+    }
+
+    // Check right away so a recovery is reported without waiting for the next interval
+    let outcome = run_check(&pool, &service).await;
 
     Ok(Json(serde_json::json!({
-        "service_id": service_id,
-        "service_name": service.service_name,
-        "status": status,
-        "checked_at": chrono::Utc::now()
+        "success": true,
+        "service_id": service.id,
+        "service_state": outcome.service_state,
+        "received_at": now
     })))
 }
 
-// Health check function for all services
-pub async fn check_all_services_health(
+// Uptime, SLO target and error budget for a single service
+pub async fn get_service_uptime(
     State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Path(service_id): Path<i64>,
 ) -> Result<Json<serde_json::Value>, Response> {
-    // Get all services
-    let services = match sqlx::query_as::<_, Service>("SELECT * FROM services ORDER BY service_name")
+    let service = fetch_service(&pool, service_id).await?;
+
+    match uptime::calculate(&pool, &service).await {
+        Ok(windows) => {
+            uptime::export_metrics(&service, &windows);
+            Ok(Json(serde_json::json!({
+                "service_id": service.id,
+                "service_name": service.service_name,
+                "slo_target": service.slo_target,
+                "windows": windows
+            })))
+        }
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to calculate uptime"))
+            .unwrap()),
+    }
+}
+
+// Notification channel management functions
+pub async fn get_notification_channels(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+) -> Result<Json<Vec<NotificationChannel>>, Response> {
+    match sqlx::query_as::<_, NotificationChannel>("SELECT * FROM notification_channels ORDER BY name")
         .fetch_all(&pool)
         .await
     {
-        Ok(services) => services,
+        Ok(channels) => Ok(Json(channels)),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to fetch notification channels"))
+            .unwrap()),
+    }
+}
+
+pub async fn create_notification_channel(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Json(channel_data): Json<CreateNotificationChannelRequest>,
+) -> Result<Json<serde_json::Value>, Response> {
+    if let Err(message) = notifications::validate_channel(&channel_data.channel_type, &channel_data.config) {
+        return Err(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(axum::body::Body::from(message))
+            .unwrap());
+    }
+
+    let now = chrono::Utc::now();
+    match sqlx::query(
+        "INSERT INTO notification_channels (name, channel_type, service_id, config, enabled, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&channel_data.name)
+    .bind(&channel_data.channel_type)
+    .bind(channel_data.service_id)
+    .bind(sqlx::types::Json(&channel_data.config))
+    .bind(channel_data.enabled.unwrap_or(true))
+    .bind(now)
+    .bind(now)
+    .execute(&pool)
+    .await
+    {
+        Ok(result) => Ok(Json(serde_json::json!({
+            "success": true,
+            "message": "Notification channel created successfully",
+            "id": result.last_insert_rowid()
+        }))),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to create notification channel"))
+            .unwrap()),
+    }
+}
+
+pub async fn update_notification_channel(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Path(channel_id): Path<i64>,
+    Json(channel_data): Json<UpdateNotificationChannelRequest>,
+) -> Result<Json<serde_json::Value>, Response> {
+    let mut channel = match sqlx::query_as::<_, NotificationChannel>("SELECT * FROM notification_channels WHERE id = ?")
+        .bind(channel_id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(channel)) => channel,
+        Ok(None) => {
+            return Err(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(axum::body::Body::from("Notification channel not found"))
+                .unwrap());
+        }
         Err(_) => {
             return Err(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(axum::body::Body::from("Failed to fetch services"))
-                .unwrap()
-                .into());
+                .body(axum::body::Body::from("Database error"))
+                .unwrap());
         }
     };
 
-    let mut results = Vec::new();
-    
-    for service in services {
-        let start = std::time::Instant::now();
-        let status = match perform_health_check(&service.healthcheck_url).await {
-            Ok(status) => status,
-            Err(_) => "DOWN".to_string(),
-        };
-        let elapsed = start.elapsed().as_secs_f64();
-        let url_label = service.healthcheck_url.clone();
-        let name_label = service.service_name.clone();
-        let labels = [&url_label[..], &name_label[..]];
-        SERVICE_LATENCY_SECONDS.with_label_values(&labels).set(elapsed);
-        SERVICE_STATUS.with_label_values(&labels).set(if status == "UP" { 1.0 } else { 0.0 });
-        SERVICE_CHECKS_TOTAL.with_label_values(&[labels[0], labels[1], if status == "UP" { "UP" } else { "DOWN" }]).inc();
-
-        results.push(serde_json::json!({
-            "service_id": service.id,
-            "service_name": service.service_name,
-            "status": status,
-            "checked_at": chrono::Utc::now()
-        }));
+    // Merge the provided fields so type and config are validated together
+    if let Some(name) = channel_data.name {
+        channel.name = name;
+    }
+    if let Some(channel_type) = channel_data.channel_type {
+        channel.channel_type = channel_type;
+    }
+    if let Some(service_id) = channel_data.service_id {
+        channel.service_id = service_id;
+    }
+    if let Some(mut config) = channel_data.config {
+        // Credentials read from GET /api/notification-channels come back redacted
+        restore_redacted(&mut config, &channel.config);
+        channel.config = sqlx::types::Json(config);
+    }
+    if let Some(enabled) = channel_data.enabled {
+        channel.enabled = enabled;
     }
 
-    Ok(Json(serde_json::json!({
-        "services": results,
-        "total_checked": results.len()
-    })))
+    if let Err(message) = notifications::validate_channel(&channel.channel_type, &channel.config) {
+        return Err(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(axum::body::Body::from(message))
+            .unwrap());
+    }
+
+    match sqlx::query(
+        "UPDATE notification_channels SET name = ?, channel_type = ?, service_id = ?, config = ?, enabled = ?, updated_at = ? WHERE id = ?"
+    )
+    .bind(&channel.name)
+    .bind(&channel.channel_type)
+    .bind(channel.service_id)
+    .bind(&channel.config)
+    .bind(channel.enabled)
+    .bind(chrono::Utc::now())
+    .bind(channel_id)
+    .execute(&pool)
+    .await
+    {
+        Ok(_) => Ok(Json(serde_json::json!({
+            "success": true,
+            "message": "Notification channel updated successfully"
+        }))),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to update notification channel"))
+            .unwrap()),
+    }
 }
 
-// Helper function to perform HTTP health check
-async fn perform_health_check(url: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    let response = client.get(url)
-        .timeout(std::time::Duration::from_secs(10))
-        .send()
-        .await?;
-    
-    if response.status().is_success() {
-        Ok("UP".to_string())
-    } else {
-        Ok("DOWN".to_string())
+pub async fn delete_notification_channel(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Path(channel_id): Path<i64>,
+) -> Result<Json<serde_json::Value>, Response> {
+    match sqlx::query("DELETE FROM notification_channels WHERE id = ?")
+        .bind(channel_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(_) => Ok(Json(serde_json::json!({
+            "success": true,
+            "message": "Notification channel deleted successfully"
+        }))),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to delete notification channel"))
+            .unwrap()),
+    }
+}
+
+pub async fn get_maintenance_windows(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+) -> Result<Json<serde_json::Value>, Response> {
+    match sqlx::query_as::<_, MaintenanceWindow>("SELECT * FROM maintenance_windows ORDER BY id")
+        .fetch_all(&pool)
+        .await
+    {
+        Ok(windows) => {
+            let now = chrono::Utc::now();
+            let windows: Vec<serde_json::Value> = windows
+                .into_iter()
+                .map(|window| {
+                    let active = window.is_active(now);
+                    let mut value = serde_json::to_value(window).unwrap_or_default();
+                    value["active"] = serde_json::Value::Bool(active);
+                    value
+                })
+                .collect();
+            Ok(Json(serde_json::json!({
+                "windows": windows,
+                "count": windows.len()
+            })))
+        }
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to fetch maintenance windows"))
+            .unwrap()),
+    }
+}
+
+pub async fn create_maintenance_window(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Json(window_data): Json<CreateMaintenanceWindowRequest>,
+) -> Result<Json<serde_json::Value>, Response> {
+    let now = chrono::Utc::now();
+    let window = MaintenanceWindow {
+        id: 0,
+        name: window_data.name,
+        service_id: window_data.service_id,
+        tag: window_data.tag,
+        starts_at: window_data.starts_at,
+        ends_at: window_data.ends_at,
+        cron_expression: window_data.cron_expression,
+        duration_minutes: window_data.duration_minutes,
+        created_at: now,
+        updated_at: now,
+    };
+    window.validate().map_err(bad_request)?;
+
+    match sqlx::query(
+        "INSERT INTO maintenance_windows (name, service_id, tag, starts_at, ends_at, cron_expression, duration_minutes, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&window.name)
+    .bind(window.service_id)
+    .bind(&window.tag)
+    .bind(window.starts_at)
+    .bind(window.ends_at)
+    .bind(&window.cron_expression)
+    .bind(window.duration_minutes)
+    .bind(now)
+    .bind(now)
+    .execute(&pool)
+    .await
+    {
+        Ok(result) => Ok(Json(serde_json::json!({
+            "success": true,
+            "message": "Maintenance window created successfully",
+            "id": result.last_insert_rowid()
+        }))),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to create maintenance window"))
+            .unwrap()),
+    }
+}
+
+pub async fn update_maintenance_window(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Path(window_id): Path<i64>,
+    Json(window_data): Json<UpdateMaintenanceWindowRequest>,
+) -> Result<Json<serde_json::Value>, Response> {
+    let mut window = match sqlx::query_as::<_, MaintenanceWindow>("SELECT * FROM maintenance_windows WHERE id = ?")
+        .bind(window_id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(window)) => window,
+        Ok(None) => {
+            return Err(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(axum::body::Body::from("Maintenance window not found"))
+                .unwrap());
+        }
+        Err(_) => {
+            return Err(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(axum::body::Body::from("Database error"))
+                .unwrap());
+        }
+    };
+
+    // Merge the provided fields so one-off and recurring settings are validated together
+    if let Some(name) = window_data.name {
+        window.name = name;
+    }
+    if let Some(service_id) = window_data.service_id {
+        window.service_id = service_id;
+    }
+    if let Some(tag) = window_data.tag {
+        window.tag = tag;
+    }
+    if let Some(starts_at) = window_data.starts_at {
+        window.starts_at = starts_at;
+    }
+    if let Some(ends_at) = window_data.ends_at {
+        window.ends_at = ends_at;
+    }
+    if let Some(expression) = window_data.cron_expression {
+        window.cron_expression = expression;
+    }
+    if let Some(minutes) = window_data.duration_minutes {
+        window.duration_minutes = minutes;
+    }
+    window.validate().map_err(bad_request)?;
+
+    match sqlx::query(
+        "UPDATE maintenance_windows SET name = ?, service_id = ?, tag = ?, starts_at = ?, ends_at = ?, cron_expression = ?, duration_minutes = ?, updated_at = ? WHERE id = ?"
+    )
+    .bind(&window.name)
+    .bind(window.service_id)
+    .bind(&window.tag)
+    .bind(window.starts_at)
+    .bind(window.ends_at)
+    .bind(&window.cron_expression)
+    .bind(window.duration_minutes)
+    .bind(chrono::Utc::now())
+    .bind(window_id)
+    .execute(&pool)
+    .await
+    {
+        Ok(_) => Ok(Json(serde_json::json!({
+            "success": true,
+            "message": "Maintenance window updated successfully"
+        }))),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to update maintenance window"))
+            .unwrap()),
+    }
+}
+
+pub async fn delete_maintenance_window(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Path(window_id): Path<i64>,
+) -> Result<Json<serde_json::Value>, Response> {
+    match sqlx::query("DELETE FROM maintenance_windows WHERE id = ?")
+        .bind(window_id)
+        .execute(&pool)
+        .await
+    {
+        Ok(_) => Ok(Json(serde_json::json!({
+            "success": true,
+            "message": "Maintenance window deleted successfully"
+        }))),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to delete maintenance window"))
+            .unwrap()),
+    }
+}
+
+// Notification delivery log, newest first
+pub async fn get_notification_deliveries(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<serde_json::Value>, Response> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    match sqlx::query_as::<_, NotificationDelivery>(
+        "SELECT * FROM notification_deliveries
+         WHERE (?1 IS NULL OR service_id = ?1)
+         ORDER BY created_at DESC
+         LIMIT ?2"
+    )
+    .bind(query.service_id)
+    .bind(limit)
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(deliveries) => Ok(Json(serde_json::json!({
+            "deliveries": deliveries,
+            "count": deliveries.len()
+        }))),
+        Err(_) => Err(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(axum::body::Body::from("Failed to fetch notification deliveries"))
+            .unwrap()),
     }
 }
 
@@ -467,13 +1111,15 @@ pub async fn health_check(
         Err(_) => Err(Response::builder()
             .status(StatusCode::SERVICE_UNAVAILABLE)
             .body(axum::body::Body::from("Database connection failed"))
-            .unwrap()
-            .into()),
+            .unwrap()),
     }
 }
 
 // Prometheus metrics handler with proper Content-Type
-pub async fn prometheus_metrics() -> Response {
+pub async fn prometheus_metrics(
+    State((pool, _sessions)): State<(SqlitePool, SessionStore)>,
+) -> Response {
+    uptime::refresh_all(&pool).await;
     let metrics_data = crate::metrics::gather_metrics();
     
     Response::builder()
@@ -496,8 +1142,7 @@ pub async fn change_password(
             return Err(Response::builder()
                 .status(StatusCode::UNAUTHORIZED)
                 .body(axum::body::Body::from("Unauthorized"))
-                .unwrap()
-                .into());
+                .unwrap());
         }
     };
 
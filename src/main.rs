@@ -1,19 +1,26 @@
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod assertions;
 mod auth;
+mod checks;
 mod config;
 mod database;
+mod email;
 mod metrics;
 mod collector;
 mod handlers;
+mod maintenance;
 mod models;
+mod notifications;
 mod routes;
+mod schedule;
+mod uptime;
 
 #[tokio::main]
 async fn main() {
     // Load configuration from environment
-    let config = config::Config::from_env();
+    let config = &*config::CONFIG;
     
     // Initialize tracing
     tracing_subscriber::registry()
@@ -32,6 +39,9 @@ async fn main() {
     // Start collectors
     collector::GLOBAL_COLLECTOR.start(pool.clone()).await;
 
+    // Keep check history within its retention period
+    database::spawn_history_pruning(pool.clone());
+
     // Create the application router
     let app = routes::create_router(pool.clone(), sessions)
         .layer(CorsLayer::permissive());
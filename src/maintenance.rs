@@ -0,0 +1,176 @@
+use chrono::{DateTime, Duration, Local, Utc};
+use sqlx::SqlitePool;
+use crate::models::{MaintenanceWindow, Service};
+use crate::schedule;
+
+impl MaintenanceWindow {
+    /// A window is either one-off (`starts_at` and `ends_at`) or recurring
+    /// (`cron_expression` and `duration_minutes`).
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Maintenance window name must not be empty".to_string());
+        }
+        if self.service_id.is_some() && self.tag.is_some() {
+            return Err("A maintenance window is scoped to a service or a tag, not both".to_string());
+        }
+
+        match (&self.starts_at, &self.ends_at, &self.cron_expression, self.duration_minutes) {
+            (Some(starts_at), Some(ends_at), None, None) => {
+                if starts_at >= ends_at {
+                    return Err("starts_at must be before ends_at".to_string());
+                }
+                Ok(())
+            }
+            (None, None, Some(expression), Some(minutes)) => {
+                if minutes <= 0 {
+                    return Err("duration_minutes must be at least 1".to_string());
+                }
+                schedule::parse_cron(expression).map(|_| ())
+            }
+            _ => Err("Set either starts_at and ends_at, or cron_expression and duration_minutes".to_string()),
+        }
+    }
+
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        if let (Some(starts_at), Some(ends_at)) = (self.starts_at, self.ends_at) {
+            return starts_at <= at && at < ends_at;
+        }
+        let (Some(expression), Some(minutes)) = (&self.cron_expression, self.duration_minutes) else {
+            return false;
+        };
+        let Ok(cron) = schedule::parse_cron(expression) else {
+            return false;
+        };
+        // The latest occurrence that could still be open is the first one
+        // after `at - duration`
+        let opened_after = (at - Duration::minutes(minutes)).with_timezone(&Local);
+        cron.after(&opened_after).next().is_some_and(|start| start.with_timezone(&Utc) <= at)
+    }
+}
+
+/// The maintenance window covering a service at `at`, if any.
+pub async fn active_window(pool: &SqlitePool, service: &Service, at: DateTime<Utc>) -> Option<MaintenanceWindow> {
+    let windows = sqlx::query_as::<_, MaintenanceWindow>(
+        "SELECT * FROM maintenance_windows
+         WHERE (service_id IS NULL OR service_id = ?1)
+           AND (tag IS NULL OR tag IN (SELECT value FROM json_each(?2)))
+         ORDER BY id"
+    )
+    .bind(service.id)
+    .bind(&service.tags)
+    .fetch_all(pool)
+    .await;
+
+    match windows {
+        Ok(windows) => windows.into_iter().find(|window| window.is_active(at)),
+        Err(e) => {
+            tracing::warn!("Failed to load maintenance windows for service {}: {}", service.id, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+
+    fn window() -> MaintenanceWindow {
+        let now = Utc::now();
+        MaintenanceWindow {
+            id: 0,
+            name: "upgrade".to_string(),
+            service_id: None,
+            tag: None,
+            starts_at: None,
+            ends_at: None,
+            cron_expression: None,
+            duration_minutes: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn one_off(starts_at: DateTime<Utc>, ends_at: DateTime<Utc>) -> MaintenanceWindow {
+        MaintenanceWindow { starts_at: Some(starts_at), ends_at: Some(ends_at), ..window() }
+    }
+
+    fn local(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Local.with_ymd_and_hms(2025, 9, 4, hour, minute, second).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn validates_schedule_and_scope() {
+        let start = Utc::now();
+        assert!(one_off(start, start + Duration::hours(1)).validate().is_ok());
+        assert!(one_off(start, start).validate().is_err());
+        assert!(MaintenanceWindow { cron_expression: Some("0 2 * * *".to_string()), duration_minutes: Some(30), ..window() }.validate().is_ok());
+        assert!(MaintenanceWindow { cron_expression: Some("0 2 * * *".to_string()), duration_minutes: Some(0), ..window() }.validate().is_err());
+        assert!(MaintenanceWindow { cron_expression: Some("0 2 * * *".to_string()), ..one_off(start, start + Duration::hours(1)) }.validate().is_err());
+        assert!(window().validate().is_err());
+        assert!(MaintenanceWindow { service_id: Some(1), tag: Some("db".to_string()), ..one_off(start, start + Duration::hours(1)) }.validate().is_err());
+    }
+
+    #[test]
+    fn one_off_window_includes_start_and_excludes_end() {
+        let start = Utc::now();
+        let end = start + Duration::hours(1);
+        let w = one_off(start, end);
+        assert!(!w.is_active(start - Duration::seconds(1)));
+        assert!(w.is_active(start));
+        assert!(w.is_active(end - Duration::seconds(1)));
+        assert!(!w.is_active(end));
+    }
+
+    #[test]
+    fn recurring_window_lasts_its_duration() {
+        let w = MaintenanceWindow { cron_expression: Some("0 2 * * *".to_string()), duration_minutes: Some(30), ..window() };
+        assert!(!w.is_active(local(1, 59, 59)));
+        assert!(w.is_active(local(2, 0, 0)));
+        assert!(w.is_active(local(2, 29, 59)));
+        assert!(!w.is_active(local(2, 30, 0)));
+    }
+
+    async fn insert_window(pool: &SqlitePool, service_id: Option<i64>, tag: Option<&str>, starts_at: DateTime<Utc>) {
+        sqlx::query("INSERT INTO maintenance_windows (name, service_id, tag, starts_at, ends_at, created_at, updated_at) VALUES ('w', ?, ?, ?, ?, ?, ?)")
+            .bind(service_id)
+            .bind(tag)
+            .bind(starts_at)
+            .bind(starts_at + Duration::hours(1))
+            .bind(starts_at)
+            .bind(starts_at)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    async fn service(pool: &SqlitePool, tags: &[&str]) -> Service {
+        let now = Utc::now();
+        let id = sqlx::query("INSERT INTO services (service_name, healthcheck_url, healthcheck_duration_seconds, tags, created_at, updated_at) VALUES ('s', 'http://127.0.0.1/', 30, ?, ?, ?)")
+            .bind(sqlx::types::Json(tags))
+            .bind(now)
+            .bind(now)
+            .execute(pool)
+            .await
+            .unwrap()
+            .last_insert_rowid();
+        sqlx::query_as("SELECT * FROM services WHERE id = ?").bind(id).fetch_one(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn active_window_matches_service_tag_and_global_scope() {
+        let pool = crate::database::init_database("sqlite::memory:", "admin", "admin").await.unwrap();
+        let tagged = service(&pool, &["payments", "db"]).await;
+        let other = service(&pool, &["search"]).await;
+        let now = Utc::now();
+
+        insert_window(&pool, None, Some("db"), now - Duration::minutes(5)).await;
+        insert_window(&pool, Some(other.id), None, now + Duration::hours(2)).await;
+        assert!(active_window(&pool, &tagged, now).await.is_some());
+        assert!(active_window(&pool, &other, now).await.is_none());
+        assert!(active_window(&pool, &other, now + Duration::minutes(150)).await.is_some());
+
+        insert_window(&pool, None, None, now - Duration::minutes(5)).await;
+        assert!(active_window(&pool, &other, now).await.is_some());
+    }
+}
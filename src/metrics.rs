@@ -1,11 +1,16 @@
+use std::collections::HashMap;
 use once_cell::sync::Lazy;
-use prometheus::{register_counter_vec, register_gauge_vec, Encoder, GaugeVec, CounterVec, TextEncoder};
+use prometheus::{register_counter_vec, register_gauge_vec, register_histogram_vec, Encoder, GaugeVec, CounterVec, HistogramVec, TextEncoder};
+use crate::checks::PhaseTimings;
+use crate::config::CONFIG;
+use crate::models::Service;
+use crate::uptime;
 
 pub static SERVICE_STATUS: Lazy<GaugeVec> = Lazy::new(|| {
     register_gauge_vec!(
         "service_status",
-        "Service health status (1=UP, 0=DOWN)",
-        &["service_health_checkurl", "service_name"]
+        "Service health status (1=UP or WARNING, 0=DOWN), held during maintenance",
+        &["service_id", "service_health_checkurl", "service_name"]
     ).expect("register service_status")
 });
 
@@ -13,18 +18,129 @@ pub static SERVICE_LATENCY_SECONDS: Lazy<GaugeVec> = Lazy::new(|| {
     register_gauge_vec!(
         "service_latency_seconds",
         "Last health check latency in seconds",
-        &["service_health_checkurl", "service_name"]
+        &["service_id", "service_health_checkurl", "service_name"]
     ).expect("register service_latency_seconds")
 });
 
+pub static SERVICE_LATENCY_HISTOGRAM: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "service_check_duration_seconds",
+        "Health check latency in seconds",
+        &["service_id", "service_health_checkurl", "service_name"],
+        CONFIG.latency_histogram_buckets.clone()
+    ).expect("register service_check_duration_seconds")
+});
+
+pub static SERVICE_PHASE_HISTOGRAM: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "service_check_phase_duration_seconds",
+        "Health check latency by phase (dns, connect, tls, ttfb) in seconds",
+        &["service_id", "service_health_checkurl", "service_name", "phase"],
+        CONFIG.latency_histogram_buckets.clone()
+    ).expect("register service_check_phase_duration_seconds")
+});
+
 pub static SERVICE_CHECKS_TOTAL: Lazy<CounterVec> = Lazy::new(|| {
     register_counter_vec!(
         "service_checks_total",
         "Total number of health checks",
-        &["service_health_checkurl", "service_name", "result"]
+        &["service_id", "service_health_checkurl", "service_name", "result"]
     ).expect("register service_checks_total")
 });
 
+pub static SERVICE_TLS_CERT_EXPIRY_DAYS: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "service_tls_cert_expiry_days",
+        "Days until the service's TLS certificate expires (negative once expired)",
+        &["service_id", "service_health_checkurl", "service_name"]
+    ).expect("register service_tls_cert_expiry_days")
+});
+
+pub static SERVICE_IN_MAINTENANCE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "service_in_maintenance",
+        "Whether the service is in a maintenance window (1) or not (0)",
+        &["service_id", "service_health_checkurl", "service_name"]
+    ).expect("register service_in_maintenance")
+});
+
+pub static SERVICE_SLO_TARGET: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "service_slo_target_ratio",
+        "Configured availability SLO target (0-1)",
+        &["service_id", "service_health_checkurl", "service_name"]
+    ).expect("register service_slo_target_ratio")
+});
+
+pub static SERVICE_UPTIME_RATIO: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "service_uptime_ratio",
+        "Share of successful checks over a rolling window (0-1)",
+        &["service_id", "service_health_checkurl", "service_name", "window"]
+    ).expect("register service_uptime_ratio")
+});
+
+pub static SERVICE_ERROR_BUDGET_REMAINING: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "service_error_budget_remaining_ratio",
+        "Remaining error budget over a rolling window (1=untouched, <0=exhausted)",
+        &["service_id", "service_health_checkurl", "service_name", "window"]
+    ).expect("register service_error_budget_remaining_ratio")
+});
+
+pub static SERVICE_ERROR_BUDGET_BURN_RATE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "service_error_budget_burn_rate",
+        "Error budget burn rate over a rolling window (1=consuming exactly the budget)",
+        &["service_id", "service_health_checkurl", "service_name", "window"]
+    ).expect("register service_error_budget_burn_rate")
+});
+
+/// Values of the `result` label of `service_checks_total`.
+const CHECK_RESULTS: [&str; 3] = ["UP", "WARNING", "DOWN"];
+
+/// URL and name each service's series were last exported with, so the old
+/// series can be dropped when they change or the service is deleted.
+static EXPORTED_LABELS: Lazy<parking_lot::Mutex<HashMap<i64, (String, String)>>> = Lazy::new(|| parking_lot::Mutex::new(HashMap::new()));
+
+/// Label values identifying a service's series, after dropping any series
+/// exported under its previous URL or name.
+pub fn service_labels(service: &Service) -> [String; 3] {
+    let current = (service.healthcheck_url.clone(), service.service_name.clone());
+    let previous = EXPORTED_LABELS.lock().insert(service.id, current.clone());
+    if let Some((url, name)) = previous.filter(|previous| *previous != current) {
+        remove_series(service.id, &url, &name);
+    }
+    [service.id.to_string(), current.0, current.1]
+}
+
+/// Drops every series of a deleted service.
+pub fn remove_service(service_id: i64) {
+    if let Some((url, name)) = EXPORTED_LABELS.lock().remove(&service_id) {
+        remove_series(service_id, &url, &name);
+    }
+}
+
+fn remove_series(service_id: i64, url: &str, name: &str) {
+    let id = service_id.to_string();
+    let labels = [id.as_str(), url, name];
+    for gauge in [&*SERVICE_STATUS, &*SERVICE_LATENCY_SECONDS, &*SERVICE_TLS_CERT_EXPIRY_DAYS, &*SERVICE_IN_MAINTENANCE, &*SERVICE_SLO_TARGET] {
+        let _ = gauge.remove_label_values(&labels);
+    }
+    let _ = SERVICE_LATENCY_HISTOGRAM.remove_label_values(&labels);
+    for phase in PhaseTimings::PHASES {
+        let _ = SERVICE_PHASE_HISTOGRAM.remove_label_values(&[labels[0], url, name, phase]);
+    }
+    for result in CHECK_RESULTS {
+        let _ = SERVICE_CHECKS_TOTAL.remove_label_values(&[labels[0], url, name, result]);
+    }
+    for (window, _) in uptime::WINDOWS {
+        for gauge in [&*SERVICE_UPTIME_RATIO, &*SERVICE_ERROR_BUDGET_REMAINING, &*SERVICE_ERROR_BUDGET_BURN_RATE] {
+            let _ = gauge.remove_label_values(&[labels[0], url, name, window]);
+        }
+    }
+}
+
 pub fn gather_metrics() -> Vec<u8> {
     let metric_families = prometheus::gather();
     let mut buffer = Vec::new();
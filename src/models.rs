@@ -1,6 +1,12 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use chrono::{DateTime, Utc};
+use crate::assertions::Assertion;
+use crate::checks::{transaction::StepResult, PhaseTimings};
+use crate::notifications;
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: i64,
@@ -22,6 +28,7 @@ pub struct LoginResponse {
     pub redirect_url: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize)]
 pub struct DashboardData {
     pub username: String,
@@ -32,17 +39,130 @@ pub struct DashboardData {
 pub struct Service {
     pub id: i64,
     pub service_name: String,
+    /// Target of the check, read according to `check_type`: a URL for `http`,
+    /// `websocket` and `transaction` (base URL), `host:port` or a URL for the
+    /// protocol checks, a host name for `dns`, the program for `command` and a
+    /// free-form description for `heartbeat`. Each type's `validate` in
+    /// `checks` defines the accepted forms.
     pub healthcheck_url: String,
     pub healthcheck_duration_seconds: i64,
+    /// Labels used to scope maintenance windows.
+    pub tags: sqlx::types::Json<Vec<String>>,
+    /// Replaces the interval when set, e.g. `*/5 9-17 * * MON-FRI`.
+    pub cron_expression: Option<String>,
+    /// When the scheduler will check the service next.
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub check_type: String,
+    /// Settings specific to the check type, e.g. the record type of a `dns`
+    /// check. Passwords and header values are redacted in responses.
+    #[serde(serialize_with = "serialize_check_config")]
+    pub check_config: sqlx::types::Json<serde_json::Value>,
+    /// Secret for `POST /api/heartbeat/:token`, set for `heartbeat` services.
+    /// Only returned when it is generated.
+    #[serde(skip_serializing)]
+    pub heartbeat_token: Option<String>,
+    pub last_heartbeat_at: Option<DateTime<Utc>>,
+    pub slo_target: f64,
+    pub failure_threshold: i64,
+    pub recovery_threshold: i64,
+    pub timeout_seconds: Option<i64>,
+    pub retries: Option<i64>,
+    pub retry_backoff_ms: Option<i64>,
+    pub tls_expiry_warning_days: Option<i64>,
+    pub assertions: sqlx::types::Json<Vec<Assertion>>,
+    pub http_method: String,
+    #[serde(serialize_with = "serialize_headers")]
+    pub request_headers: sqlx::types::Json<BTreeMap<String, String>>,
+    pub request_body: Option<String>,
+    /// Accepted status codes such as `200-299,301,401`; any 2xx when unset.
+    pub expected_status_codes: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Shown instead of secrets in API responses. Sending it back in an update
+/// keeps the stored value.
+pub const REDACTED: &str = "********";
+
+fn serialize_check_config<S: Serializer>(config: &sqlx::types::Json<Value>, serializer: S) -> Result<S::Ok, S::Error> {
+    redact_secrets(&config.0, &["password"]).serialize(serializer)
+}
+
+fn serialize_channel_config<S: Serializer>(config: &sqlx::types::Json<Value>, serializer: S) -> Result<S::Ok, S::Error> {
+    redact_secrets(&config.0, &notifications::SECRET_CONFIG_KEYS).serialize(serializer)
+}
+
+fn serialize_headers<S: Serializer>(headers: &sqlx::types::Json<BTreeMap<String, String>>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(headers.keys().map(|name| (name, REDACTED)))
+}
+
+/// Replaces the values of `secret_keys` and of every `headers` object, e.g.
+/// those of transaction steps, at any depth.
+pub fn redact_secrets(value: &Value, secret_keys: &[&str]) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| {
+                    let value = match (key.as_str(), value) {
+                        (_, Value::Null) => Value::Null,
+                        ("headers", Value::Object(headers)) => {
+                            Value::Object(headers.keys().map(|name| (name.clone(), Value::from(REDACTED))).collect())
+                        }
+                        (key, _) if secret_keys.contains(&key) => Value::from(REDACTED),
+                        _ => redact_secrets(value, secret_keys),
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(|item| redact_secrets(item, secret_keys)).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Puts the stored value back wherever an update sent [`REDACTED`].
+pub fn restore_redacted(value: &mut Value, stored: &Value) {
+    match value {
+        Value::String(text) if text == REDACTED && !stored.is_null() => *value = stored.clone(),
+        Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                restore_redacted(value, stored.get(key).unwrap_or(&Value::Null));
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                restore_redacted(item, stored.get(i).unwrap_or(&Value::Null));
+            }
+        }
+        _ => {}
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateServiceRequest {
     pub service_name: String,
     pub healthcheck_url: String,
     pub healthcheck_duration_seconds: i64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub cron_expression: Option<String>,
+    pub check_type: Option<String>,
+    pub check_config: Option<serde_json::Value>,
+    pub slo_target: Option<f64>,
+    pub failure_threshold: Option<i64>,
+    pub recovery_threshold: Option<i64>,
+    pub timeout_seconds: Option<i64>,
+    pub retries: Option<i64>,
+    pub retry_backoff_ms: Option<i64>,
+    pub tls_expiry_warning_days: Option<i64>,
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+    pub http_method: Option<String>,
+    #[serde(default)]
+    pub request_headers: BTreeMap<String, String>,
+    pub request_body: Option<String>,
+    pub expected_status_codes: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -50,6 +170,167 @@ pub struct UpdateServiceRequest {
     pub service_name: Option<String>,
     pub healthcheck_url: Option<String>,
     pub healthcheck_duration_seconds: Option<i64>,
+    pub tags: Option<Vec<String>>,
+    /// `null` goes back to the interval.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub cron_expression: Option<Option<String>>,
+    pub check_type: Option<String>,
+    pub check_config: Option<serde_json::Value>,
+    pub slo_target: Option<f64>,
+    pub failure_threshold: Option<i64>,
+    pub recovery_threshold: Option<i64>,
+    /// For the nullable overrides below, `null` resets to the global default
+    /// and a missing field leaves the value unchanged.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub timeout_seconds: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub retries: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub retry_backoff_ms: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub tls_expiry_warning_days: Option<Option<i64>>,
+    pub assertions: Option<Vec<Assertion>>,
+    pub http_method: Option<String>,
+    pub request_headers: Option<BTreeMap<String, String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub request_body: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expected_status_codes: Option<Option<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CheckResult {
+    pub id: i64,
+    pub service_id: i64,
+    pub checked_at: DateTime<Utc>,
+    pub status: String,
+    pub service_state: Option<String>,
+    pub attempts: i64,
+    pub latency_ms: f64,
+    pub http_status_code: Option<i64>,
+    pub error: Option<String>,
+    pub tls_expires_at: Option<DateTime<Utc>>,
+    pub steps: Option<sqlx::types::Json<Vec<StepResult>>>,
+    pub timings: Option<sqlx::types::Json<PhaseTimings>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct NotificationChannel {
+    pub id: i64,
+    pub name: String,
+    pub channel_type: String,
+    pub service_id: Option<i64>,
+    /// Credentials such as webhook URLs and tokens are redacted in responses.
+    #[serde(serialize_with = "serialize_channel_config")]
+    pub config: sqlx::types::Json<serde_json::Value>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNotificationChannelRequest {
+    pub name: String,
+    pub channel_type: String,
+    pub service_id: Option<i64>,
+    pub config: serde_json::Value,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNotificationChannelRequest {
+    pub name: Option<String>,
+    pub channel_type: Option<String>,
+    /// `null` makes the channel global, a missing field leaves it unchanged.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub service_id: Option<Option<i64>>,
+    pub config: Option<serde_json::Value>,
+    pub enabled: Option<bool>,
+}
+
+/// Scoped to `service_id`, to services tagged `tag`, or to every service
+/// when neither is set.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MaintenanceWindow {
+    pub id: i64,
+    pub name: String,
+    pub service_id: Option<i64>,
+    pub tag: Option<String>,
+    /// One-off windows run from `starts_at` to `ends_at`.
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    /// Recurring windows open at every occurrence of `cron_expression` and
+    /// last `duration_minutes`.
+    pub cron_expression: Option<String>,
+    pub duration_minutes: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateMaintenanceWindowRequest {
+    pub name: String,
+    pub service_id: Option<i64>,
+    pub tag: Option<String>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub cron_expression: Option<String>,
+    pub duration_minutes: Option<i64>,
+}
+
+/// `null` clears a field, a missing field leaves it unchanged.
+#[derive(Debug, Deserialize)]
+pub struct UpdateMaintenanceWindowRequest {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub service_id: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub tag: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub starts_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub ends_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub cron_expression: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub duration_minutes: Option<Option<i64>>,
+}
+
+// Distinguishes an explicit `null` from a missing field
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Deserialize::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct NotificationDelivery {
+    pub id: i64,
+    pub service_id: i64,
+    pub channel_id: Option<i64>,
+    pub channel: String,
+    pub target: String,
+    pub event: String,
+    pub success: bool,
+    pub attempts: i64,
+    pub response_status: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryQuery {
+    pub service_id: Option<i64>,
+    pub limit: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -64,3 +345,30 @@ pub struct ChangePasswordResponse {
     pub success: bool,
     pub message: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn redacts_and_restores_secrets() {
+        let stored = json!({
+            "username": "monitor",
+            "password": "hunter2",
+            "steps": [{"url": "/login", "headers": {"Authorization": "Bearer abc"}}],
+        });
+        let mut redacted = redact_secrets(&stored, &["password"]);
+        assert_eq!(redacted, json!({
+            "username": "monitor",
+            "password": REDACTED,
+            "steps": [{"url": "/login", "headers": {"Authorization": REDACTED}}],
+        }));
+
+        redacted["username"] = json!("checker");
+        restore_redacted(&mut redacted, &stored);
+        assert_eq!(redacted["password"], "hunter2");
+        assert_eq!(redacted["steps"][0]["headers"]["Authorization"], "Bearer abc");
+        assert_eq!(redacted["username"], "checker");
+    }
+}
@@ -0,0 +1,756 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use chrono::{DateTime, Utc};
+use crate::config::{self, CONFIG};
+use crate::email;
+use crate::models::NotificationChannel;
+
+/// Shared by all HTTP channels so deliveries reuse connections.
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to build HTTP client")
+});
+
+/// Longest wait between two delivery attempts, however many retries are configured.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Channel types accepted by the `/api/notification-channels` API.
+pub const CHANNEL_TYPES: [&str; 8] = ["webhook", "slack", "teams", "discord", "telegram", "email", "pagerduty", "opsgenie"];
+
+/// Channel config keys that hold credentials; webhook URLs embed their token.
+pub const SECRET_CONFIG_KEYS: [&str; 4] = ["url", "bot_token", "routing_key", "api_key"];
+
+/// A service moving from one state to another (e.g. "UP" -> "DOWN").
+#[derive(Debug, Clone, Serialize)]
+pub struct StateChange {
+    pub service_id: i64,
+    pub service_name: String,
+    pub healthcheck_url: String,
+    pub old_state: String,
+    pub new_state: String,
+    pub latency_ms: f64,
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A rendered message ready to be sent to one destination.
+#[derive(Debug, Clone)]
+struct Delivery {
+    channel_id: Option<i64>,
+    channel: String,
+    /// What the delivery log shows as the destination; kept free of secrets.
+    target: String,
+    message: Message,
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    Http { url: String, headers: Vec<(String, String)>, payload: Value },
+    Email { to: Vec<String>, subject: String, body: String },
+}
+
+/// Queues a state change for every configured channel. Only loading the
+/// channels is awaited; messages are sent in the background.
+pub async fn notify(pool: &SqlitePool, change: &StateChange) {
+    tracing::info!(
+        "Service {} ({}) changed state: {} -> {}",
+        change.service_name, change.service_id, change.old_state, change.new_state
+    );
+
+    for url in &CONFIG.alert_webhook_urls {
+        enqueue_delivery(pool, change, Delivery {
+            channel_id: None,
+            channel: "webhook".to_string(),
+            target: url_host(url),
+            message: Message::Http { url: url.clone(), headers: Vec::new(), payload: webhook_payload(change) },
+        });
+    }
+
+    if !CONFIG.alert_email_to.is_empty() {
+        enqueue_delivery(pool, change, Delivery {
+            channel_id: None,
+            channel: "email".to_string(),
+            target: CONFIG.alert_email_to.join(", "),
+            message: email_message(change, CONFIG.alert_email_to.clone(), &Value::Null),
+        });
+    }
+
+    // Global channels plus the ones attached to this service
+    let channels = match sqlx::query_as::<_, NotificationChannel>(
+        "SELECT * FROM notification_channels WHERE enabled = 1 AND (service_id IS NULL OR service_id = ?)"
+    )
+    .bind(change.service_id)
+    .fetch_all(pool)
+    .await
+    {
+        Ok(channels) => channels,
+        Err(e) => {
+            tracing::warn!("Failed to load notification channels: {}", e);
+            return;
+        }
+    };
+
+    for channel in channels {
+        match render(&channel, change) {
+            Some(deliveries) => {
+                for delivery in deliveries {
+                    enqueue_delivery(pool, change, delivery);
+                }
+            }
+            None => tracing::warn!("Notification channel {} ({}) is misconfigured", channel.name, channel.id),
+        }
+    }
+}
+
+/// Checks that a channel's config has what its type needs.
+pub fn validate_channel(channel_type: &str, config: &Value) -> Result<(), String> {
+    let required: &[&str] = match channel_type {
+        "webhook" | "slack" | "teams" | "discord" => &["url"],
+        "telegram" => &["bot_token", "chat_id"],
+        "email" => &["to"],
+        "pagerduty" => &["routing_key"],
+        "opsgenie" => &["api_key"],
+        _ => return Err(format!("Unknown channel type '{}', expected one of: {}", channel_type, CHANNEL_TYPES.join(", "))),
+    };
+
+    for key in required {
+        // Telegram chat ids are commonly given as numbers
+        let present = match config.get(key) {
+            Some(Value::String(s)) => !s.is_empty(),
+            Some(Value::Number(_)) => true,
+            Some(Value::Array(items)) => !items.is_empty(),
+            _ => false,
+        };
+        if !present {
+            return Err(format!("Channel type '{}' requires config.{}", channel_type, key));
+        }
+    }
+    Ok(())
+}
+
+fn config_str<'a>(config: &'a Value, key: &str) -> Option<&'a str> {
+    config.get(key).and_then(Value::as_str).filter(|v| !v.is_empty())
+}
+
+/// Most channels get one message per transition; PagerDuty and Opsgenie get
+/// one per incident opened or closed. `None` when the config is incomplete.
+fn render(channel: &NotificationChannel, change: &StateChange) -> Option<Vec<Delivery>> {
+    let config = &channel.config.0;
+    let delivery = |target: String, message: Message| Delivery {
+        channel_id: Some(channel.id),
+        channel: channel.channel_type.clone(),
+        target,
+        message,
+    };
+
+    if channel.channel_type == "email" {
+        let to = recipients(config.get("to")?);
+        if to.is_empty() {
+            return None;
+        }
+        return Some(vec![delivery(to.join(", "), email_message(change, to, config))]);
+    }
+
+    let (url, target, payload) = match channel.channel_type.as_str() {
+        "webhook" => (config_str(config, "url")?.to_string(), channel.name.clone(), webhook_payload(change)),
+        "slack" => (config_str(config, "url")?.to_string(), channel.name.clone(), slack_payload(change)),
+        "teams" => (config_str(config, "url")?.to_string(), channel.name.clone(), teams_payload(change)),
+        "discord" => (config_str(config, "url")?.to_string(), channel.name.clone(), discord_payload(change)),
+        "telegram" => {
+            let token = config_str(config, "bot_token")?;
+            let chat_id = match config.get("chat_id")? {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            let api_url = config_str(config, "api_url").unwrap_or("https://api.telegram.org");
+            (
+                format!("{}/bot{}/sendMessage", api_url.trim_end_matches('/'), token),
+                format!("telegram chat {}", chat_id),
+                telegram_payload(change, &chat_id),
+            )
+        }
+        "pagerduty" => {
+            let routing_key = config_str(config, "routing_key")?;
+            let url = config_str(config, "events_url").unwrap_or("https://events.pagerduty.com/v2/enqueue");
+            let severity = config_str(config, "severity").unwrap_or("critical");
+            return Some(
+                incident_events(change)
+                    .into_iter()
+                    .map(|(incident, open)| {
+                        let severity = if incident == "WARNING" { "warning" } else { severity };
+                        let payload = pagerduty_payload(change, routing_key, incident, open, severity);
+                        delivery(channel.name.clone(), Message::Http { url: url.to_string(), headers: Vec::new(), payload })
+                    })
+                    .collect(),
+            );
+        }
+        "opsgenie" => {
+            let api_key = config_str(config, "api_key")?;
+            let api_url = config_str(config, "api_url").unwrap_or("https://api.opsgenie.com").trim_end_matches('/');
+            let priority = config_str(config, "priority").unwrap_or("P1");
+            let headers = vec![("Authorization".to_string(), format!("GenieKey {}", api_key))];
+            return Some(
+                incident_events(change)
+                    .into_iter()
+                    .map(|(incident, open)| {
+                        let url = if open {
+                            format!("{}/v2/alerts", api_url)
+                        } else {
+                            format!("{}/v2/alerts/{}/close?identifierType=alias", api_url, dedup_key(change, incident))
+                        };
+                        let priority = if incident == "WARNING" { "P3" } else { priority };
+                        let payload = opsgenie_payload(change, incident, open, priority);
+                        delivery(channel.name.clone(), Message::Http { url, headers: headers.clone(), payload })
+                    })
+                    .collect(),
+            );
+        }
+        _ => return None,
+    };
+
+    Some(vec![delivery(target, Message::Http { url, headers: Vec::new(), payload })])
+}
+
+/// Host and port of a webhook URL, which is all the delivery log shows of it.
+fn url_host(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => "webhook".to_string(),
+        },
+        Err(_) => "webhook".to_string(),
+    }
+}
+
+/// Accepts recipients as a JSON array or a comma-separated string.
+fn recipients(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(list) => config::split_list(list),
+        Value::Array(items) => items.iter().filter_map(Value::as_str).flat_map(config::split_list).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Renders the DOWN, WARNING or RECOVERED email, preferring templates from the channel config.
+fn email_message(change: &StateChange, to: Vec<String>, config: &Value) -> Message {
+    let (subject_key, body_key, default_subject, default_body) = by_severity(
+        change,
+        ("subject_recovered", "body_recovered", email::DEFAULT_SUBJECT_RECOVERED, email::DEFAULT_BODY_RECOVERED),
+        ("subject_warning", "body_warning", email::DEFAULT_SUBJECT_WARNING, email::DEFAULT_BODY_WARNING),
+        ("subject_down", "body_down", email::DEFAULT_SUBJECT_DOWN, email::DEFAULT_BODY_DOWN),
+    );
+
+    Message::Email {
+        to,
+        subject: email::render_template(config_str(config, subject_key).unwrap_or(default_subject), change),
+        body: email::render_template(config_str(config, body_key).unwrap_or(default_body), change),
+    }
+}
+
+/// Incidents to close and open for a transition, as (state, open). DOWN
+/// and WARNING each have their own incident, so leaving DOWN for WARNING
+/// resolves the outage and opens a lower-severity warning.
+fn incident_events(change: &StateChange) -> Vec<(&str, bool)> {
+    let is_incident = |state: &str| state == "DOWN" || state == "WARNING";
+    let mut events = Vec::new();
+    if is_incident(&change.old_state) {
+        events.push((change.old_state.as_str(), false));
+    }
+    if is_incident(&change.new_state) {
+        events.push((change.new_state.as_str(), true));
+    }
+    events
+}
+
+/// Picks the value for a recovery, a warning or an outage.
+fn by_severity<T>(change: &StateChange, recovered: T, warning: T, down: T) -> T {
+    match change.new_state.as_str() {
+        "UP" => recovered,
+        "WARNING" => warning,
+        _ => down,
+    }
+}
+
+fn headline(change: &StateChange) -> String {
+    format!("{} is {} (was {})", change.service_name, change.new_state, change.old_state)
+}
+
+fn webhook_payload(change: &StateChange) -> Value {
+    serde_json::json!({
+        "event": "service_state_changed",
+        "service_id": change.service_id,
+        "service_name": change.service_name,
+        "healthcheck_url": change.healthcheck_url,
+        "old_state": change.old_state,
+        "new_state": change.new_state,
+        "latency_ms": change.latency_ms,
+        "error": change.error,
+        "timestamp": change.timestamp,
+    })
+}
+
+fn slack_payload(change: &StateChange) -> Value {
+    let mut fields = vec![
+        serde_json::json!({"title": "URL", "value": change.healthcheck_url, "short": false}),
+        serde_json::json!({"title": "Latency", "value": format!("{:.0} ms", change.latency_ms), "short": true}),
+    ];
+    if let Some(error) = &change.error {
+        fields.push(serde_json::json!({"title": "Error", "value": error, "short": false}));
+    }
+
+    serde_json::json!({
+        "text": headline(change),
+        "attachments": [{
+            "color": by_severity(change, "good", "warning", "danger"),
+            "fields": fields,
+            "ts": change.timestamp.timestamp(),
+        }]
+    })
+}
+
+fn teams_payload(change: &StateChange) -> Value {
+    let mut facts = vec![
+        serde_json::json!({"name": "URL", "value": change.healthcheck_url}),
+        serde_json::json!({"name": "Latency", "value": format!("{:.0} ms", change.latency_ms)}),
+        serde_json::json!({"name": "Time", "value": change.timestamp.to_rfc3339()}),
+    ];
+    if let Some(error) = &change.error {
+        facts.push(serde_json::json!({"name": "Error", "value": error}));
+    }
+
+    serde_json::json!({
+        "@type": "MessageCard",
+        "@context": "https://schema.org/extensions",
+        "summary": headline(change),
+        "themeColor": by_severity(change, "2EB886", "DAA038", "D00000"),
+        "sections": [{
+            "activityTitle": headline(change),
+            "facts": facts,
+        }]
+    })
+}
+
+fn discord_payload(change: &StateChange) -> Value {
+    let mut fields = vec![
+        serde_json::json!({"name": "URL", "value": change.healthcheck_url, "inline": false}),
+        serde_json::json!({"name": "Latency", "value": format!("{:.0} ms", change.latency_ms), "inline": true}),
+    ];
+    if let Some(error) = &change.error {
+        fields.push(serde_json::json!({"name": "Error", "value": error, "inline": false}));
+    }
+
+    serde_json::json!({
+        "embeds": [{
+            "title": headline(change),
+            "color": by_severity(change, 0x2EB886, 0xDAA038, 0xD00000),
+            "fields": fields,
+            "timestamp": change.timestamp.to_rfc3339(),
+        }]
+    })
+}
+
+fn telegram_payload(change: &StateChange, chat_id: &str) -> Value {
+    let mut text = format!(
+        "<b>{}</b>\nURL: {}\nLatency: {:.0} ms",
+        escape_html(&headline(change)),
+        escape_html(&change.healthcheck_url),
+        change.latency_ms
+    );
+    if let Some(error) = &change.error {
+        text.push_str(&format!("\nError: {}", escape_html(error)));
+    }
+
+    serde_json::json!({
+        "chat_id": chat_id,
+        "text": text,
+        "parse_mode": "HTML",
+        "disable_web_page_preview": true,
+    })
+}
+
+/// Incident key shared by the trigger and resolve events of a service, so
+/// a recovery closes the incident its outage opened. Warnings use their own
+/// key so they never merge with an outage.
+fn dedup_key(change: &StateChange, incident: &str) -> String {
+    match incident {
+        "WARNING" => format!("nx-health-checker-service-{}-warning", change.service_id),
+        _ => format!("nx-health-checker-service-{}", change.service_id),
+    }
+}
+
+fn pagerduty_payload(change: &StateChange, routing_key: &str, incident: &str, open: bool, severity: &str) -> Value {
+    if !open {
+        return serde_json::json!({
+            "routing_key": routing_key,
+            "event_action": "resolve",
+            "dedup_key": dedup_key(change, incident),
+        });
+    }
+
+    serde_json::json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "dedup_key": dedup_key(change, incident),
+        "client": "nx-health-checker",
+        "payload": {
+            "summary": headline(change),
+            "source": change.healthcheck_url,
+            "severity": severity,
+            "timestamp": change.timestamp.to_rfc3339(),
+            "component": change.service_name,
+            "custom_details": {
+                "service_id": change.service_id,
+                "old_state": change.old_state,
+                "new_state": change.new_state,
+                "latency_ms": change.latency_ms,
+                "error": change.error,
+            }
+        }
+    })
+}
+
+fn opsgenie_payload(change: &StateChange, incident: &str, open: bool, priority: &str) -> Value {
+    if !open {
+        return serde_json::json!({
+            "source": "nx-health-checker",
+            "note": headline(change),
+        });
+    }
+
+    serde_json::json!({
+        "message": headline(change),
+        "alias": dedup_key(change, incident),
+        "description": change.error.clone().unwrap_or_else(|| headline(change)),
+        "priority": priority,
+        "source": "nx-health-checker",
+        "entity": change.service_name,
+        "details": {
+            "service_id": change.service_id.to_string(),
+            "healthcheck_url": change.healthcheck_url,
+            "old_state": change.old_state,
+            "new_state": change.new_state,
+            "latency_ms": format!("{:.0}", change.latency_ms),
+        }
+    })
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+type QueueKey = (Option<i64>, String, String, i64);
+type Job = (SqlitePool, StateChange, Delivery);
+
+/// Pending deliveries per destination and service, each drained by one task.
+static QUEUES: Lazy<Mutex<HashMap<QueueKey, mpsc::UnboundedSender<Job>>>> = Lazy::new(Default::default);
+
+/// Queues a delivery behind earlier ones to the same destination about the
+/// same service, so a resolve never overtakes the trigger it closes while
+/// that is being retried. Other destinations and services are delivered in
+/// parallel.
+fn enqueue_delivery(pool: &SqlitePool, change: &StateChange, delivery: Delivery) {
+    let key = (delivery.channel_id, delivery.channel.clone(), delivery.target.clone(), change.service_id);
+    let job = (pool.clone(), change.clone(), delivery);
+    let mut queues = QUEUES.lock();
+    let job = match queues.get(&key) {
+        Some(sender) => match sender.send(job) {
+            Ok(()) => return,
+            Err(mpsc::error::SendError(job)) => job,
+        },
+        None => job,
+    };
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let _ = sender.send(job);
+    queues.insert(key.clone(), sender);
+    tokio::spawn(drain_queue(key, receiver));
+}
+
+/// Delivers queued messages one at a time and removes the queue once it is
+/// empty. The emptiness check happens under the lock `enqueue_delivery`
+/// sends under, so no message is left behind.
+async fn drain_queue(key: QueueKey, mut receiver: mpsc::UnboundedReceiver<Job>) {
+    loop {
+        let job = match receiver.try_recv() {
+            Ok(job) => job,
+            Err(_) => {
+                let mut queues = QUEUES.lock();
+                match receiver.try_recv() {
+                    Ok(job) => job,
+                    Err(_) => {
+                        queues.remove(&key);
+                        return;
+                    }
+                }
+            }
+        };
+        let (pool, change, delivery) = job;
+        deliver(&pool, &change, &delivery).await;
+    }
+}
+
+/// Sends a message with retries and exponential backoff, then records the
+/// delivery in the `notification_deliveries` table.
+async fn deliver(pool: &SqlitePool, change: &StateChange, delivery: &Delivery) {
+    let max_attempts = CONFIG.alert_retries + 1;
+    let mut attempts = 0;
+    let mut response_status = None;
+    let mut error = None;
+
+    while attempts < max_attempts {
+        if attempts > 0 {
+            let backoff = CONFIG.alert_retry_backoff_seconds.saturating_mul(2u64.saturating_pow(attempts - 1));
+            tokio::time::sleep(Duration::from_secs(backoff).min(MAX_RETRY_BACKOFF)).await;
+        }
+        attempts += 1;
+
+        match &delivery.message {
+            Message::Http { url, headers, payload } => {
+                let mut request = CLIENT.post(url).json(payload);
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+                match request.send().await {
+                    Ok(resp) => {
+                        response_status = Some(resp.status().as_u16());
+                        if resp.status().is_success() {
+                            error = None;
+                            break;
+                        }
+                        error = Some(format!("Unexpected status code {}", resp.status()));
+                    }
+                    Err(e) => {
+                        response_status = None;
+                        // Channel URLs can embed tokens, so keep them out of the log
+                        error = Some(e.without_url().to_string());
+                    }
+                }
+            }
+            Message::Email { to, subject, body } => match email::send(to, subject, body).await {
+                Ok(()) => {
+                    error = None;
+                    break;
+                }
+                Err(e) => error = Some(e),
+            },
+        }
+    }
+
+    let success = error.is_none();
+    if let Some(e) = &error {
+        tracing::warn!("Failed to deliver {} notification to {} after {} attempts: {}", delivery.channel, delivery.target, attempts, e);
+    }
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO notification_deliveries (service_id, channel_id, channel, target, event, success, attempts, response_status, error, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(change.service_id)
+    .bind(delivery.channel_id)
+    .bind(&delivery.channel)
+    .bind(&delivery.target)
+    .bind(&change.new_state)
+    .bind(success)
+    .bind(attempts as i64)
+    .bind(response_status.map(i64::from))
+    .bind(&error)
+    .bind(Utc::now())
+    .execute(pool)
+    .await
+    {
+        tracing::warn!("Failed to record notification delivery: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::database;
+    use super::*;
+
+    fn change(old_state: &str, new_state: &str) -> StateChange {
+        StateChange {
+            service_id: 1,
+            service_name: "api".to_string(),
+            healthcheck_url: "https://api.example.com/health".to_string(),
+            old_state: old_state.to_string(),
+            new_state: new_state.to_string(),
+            latency_ms: 12.0,
+            error: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn channel(channel_type: &str, config: Value) -> NotificationChannel {
+        NotificationChannel {
+            id: 1,
+            name: "ops".to_string(),
+            channel_type: channel_type.to_string(),
+            service_id: None,
+            config: sqlx::types::Json(config),
+            enabled: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn rendered(channel: &NotificationChannel, change: &StateChange) -> Vec<(String, Message)> {
+        render(channel, change).unwrap().into_iter().map(|delivery| (delivery.target, delivery.message)).collect()
+    }
+
+    #[test]
+    fn emails_prefer_channel_templates_over_the_defaults() {
+        let channel = channel("email", serde_json::json!({
+            "to": "oncall@example.com, ops@example.com",
+            "subject_down": "{{service_name}} down",
+        }));
+        let email = |change: &StateChange| match &rendered(&channel, change)[..] {
+            [(target, Message::Email { to, subject, body })] => {
+                assert_eq!(target, "oncall@example.com, ops@example.com");
+                assert_eq!(to, &["oncall@example.com", "ops@example.com"]);
+                (subject.clone(), body.clone())
+            }
+            other => panic!("expected one email, got {:?}", other),
+        };
+
+        let down = change("UP", "DOWN");
+        assert_eq!(email(&down), ("api down".to_string(), email::render_template(email::DEFAULT_BODY_DOWN, &down)));
+        assert_eq!(email(&change("UP", "WARNING")).0, "[WARNING] api needs attention");
+        assert_eq!(email(&change("DOWN", "UP")).0, "[RECOVERED] api is back UP");
+    }
+
+    #[test]
+    fn chat_channels_post_to_their_webhook() {
+        let url = "https://hooks.example.com/services/T000/B000/XXXX";
+        let mut down = change("UP", "DOWN");
+        down.error = Some("Unexpected status code 503".to_string());
+
+        let payload = |channel_type: &str, change: &StateChange| match &rendered(&channel(channel_type, serde_json::json!({"url": url})), change)[..] {
+            [(target, Message::Http { url: posted_to, headers, payload })] => {
+                assert_eq!((target.as_str(), posted_to.as_str()), ("ops", url));
+                assert!(headers.is_empty());
+                payload.clone()
+            }
+            other => panic!("expected one post, got {:?}", other),
+        };
+
+        let slack = payload("slack", &down);
+        assert_eq!(slack["text"], "api is DOWN (was UP)");
+        assert_eq!(slack["attachments"][0]["color"], "danger");
+        assert_eq!(slack["attachments"][0]["fields"][2]["value"], "Unexpected status code 503");
+        assert_eq!(payload("slack", &change("DOWN", "UP"))["attachments"][0]["color"], "good");
+
+        let teams = payload("teams", &change("UP", "WARNING"));
+        assert_eq!(teams["summary"], "api is WARNING (was UP)");
+        assert_eq!(teams["themeColor"], "DAA038");
+
+        let discord = payload("discord", &down);
+        assert_eq!(discord["embeds"][0]["title"], "api is DOWN (was UP)");
+        assert_eq!(discord["embeds"][0]["color"], 0xD00000);
+
+        let webhook = payload("webhook", &down);
+        assert_eq!(webhook["event"], "service_state_changed");
+        assert_eq!((&webhook["old_state"], &webhook["new_state"]), (&serde_json::json!("UP"), &serde_json::json!("DOWN")));
+    }
+
+    #[test]
+    fn telegram_escapes_the_message() {
+        let channel = channel("telegram", serde_json::json!({"bot_token": "123:secret", "chat_id": -1001, "api_url": "http://127.0.0.1:8081/"}));
+        let mut change = change("UP", "DOWN");
+        change.service_name = "<api>".to_string();
+        change.error = Some("a & b".to_string());
+
+        match &rendered(&channel, &change)[..] {
+            [(target, Message::Http { url, payload, .. })] => {
+                assert_eq!(target, "telegram chat -1001");
+                assert_eq!(url, "http://127.0.0.1:8081/bot123:secret/sendMessage");
+                assert_eq!(payload["chat_id"], "-1001");
+                assert_eq!(payload["parse_mode"], "HTML");
+                assert_eq!(payload["text"], "<b>&lt;api&gt; is DOWN (was UP)</b>\nURL: https://api.example.com/health\nLatency: 12 ms\nError: a &amp; b");
+            }
+            other => panic!("expected one post, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn incomplete_channels_render_nothing() {
+        assert!(render(&channel("slack", serde_json::json!({})), &change("UP", "DOWN")).is_none());
+        assert!(render(&channel("telegram", serde_json::json!({"bot_token": "123:secret"})), &change("UP", "DOWN")).is_none());
+    }
+
+    #[test]
+    fn leaving_down_closes_the_outage() {
+        assert_eq!(incident_events(&change("UP", "DOWN")), [("DOWN", true)]);
+        assert_eq!(incident_events(&change("DOWN", "UP")), [("DOWN", false)]);
+        assert_eq!(incident_events(&change("DOWN", "WARNING")), [("DOWN", false), ("WARNING", true)]);
+        assert_eq!(incident_events(&change("WARNING", "DOWN")), [("WARNING", false), ("DOWN", true)]);
+        assert_eq!(incident_events(&change("WARNING", "UP")), [("WARNING", false)]);
+    }
+
+    #[test]
+    fn logs_webhooks_by_host() {
+        assert_eq!(url_host("https://hooks.example.com/alerts?token=secret"), "hooks.example.com");
+        assert_eq!(url_host("http://127.0.0.1:8080/T000/B000/XXXX"), "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn warnings_have_their_own_incident() {
+        let change = change("DOWN", "WARNING");
+        assert_ne!(dedup_key(&change, "WARNING"), dedup_key(&change, "DOWN"));
+    }
+
+    /// Answers webhook posts with 500 the first time and 200 afterwards,
+    /// reporting the `event_action` of each.
+    async fn flaky_endpoint() -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/enqueue", listener.local_addr().unwrap());
+        let (events, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for status in std::iter::once("500 Internal Server Error").chain(std::iter::repeat("200 OK")) {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                while !String::from_utf8_lossy(&request).contains('}') {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let body: Value = serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+                events.send(body["event_action"].as_str().unwrap().to_string()).unwrap();
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn resolve_waits_for_a_retried_trigger() {
+        let pool = database::init_database("sqlite::memory:", "admin", "admin").await.unwrap();
+        let (url, mut received) = flaky_endpoint().await;
+        let delivery = |action: &str| Delivery {
+            channel_id: Some(1),
+            channel: "pagerduty".to_string(),
+            target: "pd".to_string(),
+            message: Message::Http { url: url.clone(), headers: Vec::new(), payload: serde_json::json!({"event_action": action}) },
+        };
+
+        enqueue_delivery(&pool, &change("UP", "DOWN"), delivery("trigger"));
+        enqueue_delivery(&pool, &change("DOWN", "UP"), delivery("resolve"));
+
+        let mut events = Vec::new();
+        while events.len() < 3 {
+            events.push(tokio::time::timeout(Duration::from_secs(30), received.recv()).await.unwrap().unwrap());
+        }
+        assert_eq!(events, ["trigger", "trigger", "resolve"]);
+    }
+}
@@ -27,6 +27,20 @@ pub fn create_router(pool: SqlitePool, sessions: SessionStore) -> Router {
         .route("/health", get(handlers::health_check))
         .route("/api/services/:id/health", get(handlers::check_service_health))
         .route("/api/services/health", get(handlers::check_all_services_health))
+        .route("/api/services/:id/history", get(handlers::get_service_history))
+        .route("/api/services/:id/uptime", get(handlers::get_service_uptime))
+        .route("/api/heartbeat/:token", get(handlers::receive_heartbeat).post(handlers::receive_heartbeat))
+        // Notification routes
+        .route("/api/notification-channels", get(handlers::get_notification_channels))
+        .route("/api/notification-channels", post(handlers::create_notification_channel))
+        .route("/api/notification-channels/:id", put(handlers::update_notification_channel))
+        .route("/api/notification-channels/:id", delete(handlers::delete_notification_channel))
+        .route("/api/notification-deliveries", get(handlers::get_notification_deliveries))
+        // Maintenance window routes
+        .route("/api/maintenance", get(handlers::get_maintenance_windows))
+        .route("/api/maintenance", post(handlers::create_maintenance_window))
+        .route("/api/maintenance/:id", put(handlers::update_maintenance_window))
+        .route("/api/maintenance/:id", delete(handlers::delete_maintenance_window))
         .route("/metrics", get(handlers::prometheus_metrics))
         .route("/api/metrics/restart", post({
             let pool2 = pool.clone();
@@ -0,0 +1,146 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use chrono::{DateTime, Duration, Local, Utc};
+use cron::Schedule;
+use rand::Rng;
+use crate::checks::heartbeat;
+use crate::config::CONFIG;
+use crate::models::Service;
+
+/// Parses a cron expression. Standard five-field expressions are accepted
+/// as well as the six- and seven-field forms with seconds and years. Days of
+/// the week are numbered 0-7 with Sunday as 0 and 7, as in standard cron.
+pub fn parse_cron(expression: &str) -> Result<Schedule, String> {
+    let expression = expression.trim();
+    let invalid = |e: String| format!("Invalid cron expression {:?}: {}", expression, e);
+    let mut fields: Vec<String> = expression.split_whitespace().map(str::to_string).collect();
+    if fields.len() == 5 {
+        fields.insert(0, "0".to_string());
+    }
+    if let Some(weekdays) = fields.get_mut(5) {
+        *weekdays = weekday_names(weekdays).map_err(invalid)?;
+    }
+    Schedule::from_str(&fields.join(" ")).map_err(|e| invalid(e.to_string()))
+}
+
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Spells out numeric days of the week, as the cron crate counts them from
+/// Sunday = 1. Named days and `*` are left alone.
+fn weekday_names(field: &str) -> Result<String, String> {
+    let items = field.split(',').map(|item| {
+        let (range, step) = item.split_once('/').unwrap_or((item, ""));
+        let bounds = if range == "*" {
+            (!step.is_empty()).then_some((0, 6))
+        } else if let Some((lo, hi)) = range.split_once('-') {
+            lo.parse::<usize>().ok().zip(hi.parse::<usize>().ok())
+        } else {
+            // `5/2` runs from day 5 to the end of the week
+            range.parse::<usize>().ok().map(|day| (day, if step.is_empty() { day } else { 6 }))
+        };
+        let Some((lo, hi)) = bounds else {
+            return Ok(item.to_string());
+        };
+        let step = match step {
+            "" => 1,
+            step => step.parse().ok().filter(|step| *step > 0).ok_or_else(|| format!("Invalid step in day of week {:?}", item))?,
+        };
+        if lo > hi || hi > 7 {
+            return Err(format!("Invalid day of week {:?}, expected 0-7 or SUN-SAT", item));
+        }
+        let days: BTreeSet<usize> = (lo..=hi).step_by(step).map(|day| day % 7).collect();
+        Ok(days.into_iter().map(|day| WEEKDAYS[day]).collect::<Vec<_>>().join(","))
+    });
+    Ok(items.collect::<Result<Vec<_>, String>>()?.join(","))
+}
+
+fn interval(service: &Service) -> Duration {
+    Duration::seconds(if service.healthcheck_duration_seconds <= 0 { 30 } else { service.healthcheck_duration_seconds })
+}
+
+/// Next cron occurrence after `after` in the server's local time zone, with
+/// up to `CRON_JITTER_SECONDS` added so services sharing an expression do not
+/// all fire at once.
+fn next_cron_run(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let schedule = parse_cron(expression).ok()?;
+    let next = schedule.after(&after.with_timezone(&Local)).next()?;
+    Some(next.with_timezone(&Utc) + jitter(CONFIG.cron_jitter_seconds))
+}
+
+fn jitter(max_seconds: u64) -> Duration {
+    Duration::milliseconds(rand::thread_rng().gen_range(0..=max_seconds.saturating_mul(1000)) as i64)
+}
+
+/// Heartbeat services are also checked just after their deadline, so a
+/// missed heartbeat is reported when it is missed rather than up to a whole
+/// interval later.
+fn until_heartbeat_deadline(service: &Service, next: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+    if service.check_type != "heartbeat" {
+        return next;
+    }
+    let due = heartbeat::deadline(service) + Duration::seconds(1);
+    if due > now && due < next { due } else { next }
+}
+
+/// When a service is first checked. With `spread` interval checks start at
+/// a random point within their first interval, otherwise right away.
+pub fn first_run(service: &Service, now: DateTime<Utc>, spread: bool) -> Option<DateTime<Utc>> {
+    match &service.cron_expression {
+        Some(expression) => next_cron_run(expression, now),
+        None if spread => Some(until_heartbeat_deadline(service, now + jitter(interval(service).num_seconds() as u64), now)),
+        None => Some(now),
+    }
+}
+
+/// When a service is checked next after the run scheduled for `previous`.
+/// Interval checks keep a fixed rate unless they fell behind. `None` means
+/// the cron expression has no further occurrences.
+pub fn next_run(service: &Service, previous: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match &service.cron_expression {
+        Some(expression) => next_cron_run(expression, now),
+        None => {
+            let next = previous + interval(service);
+            let next = if next > now { next } else { now + interval(service) };
+            Some(until_heartbeat_deadline(service, next, now))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, TimeZone, Weekday};
+    use super::*;
+
+    /// Days of the week of the next week of occurrences, starting on a Sunday.
+    fn weekdays(expression: &str) -> Vec<Weekday> {
+        let sunday = Utc.with_ymd_and_hms(2026, 10, 18, 0, 0, 0).unwrap();
+        let end = sunday + Duration::days(7);
+        parse_cron(expression).unwrap().after(&sunday).take_while(|next| *next < end).map(|next| next.weekday()).collect()
+    }
+
+    #[test]
+    fn numbers_weekdays_from_sunday_zero() {
+        use Weekday::*;
+        assert_eq!(weekdays("0 9 * * 1-5"), [Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(weekdays("0 9 * * 0"), [Sun]);
+        assert_eq!(weekdays("0 9 * * 7"), [Sun]);
+        assert_eq!(weekdays("0 9 * * 5-7"), [Sun, Fri, Sat]);
+        assert_eq!(weekdays("0 9 * * */2"), [Sun, Tue, Thu, Sat]);
+        assert_eq!(weekdays("0 0 9 * * 6"), [Sat]);
+    }
+
+    #[test]
+    fn accepts_weekday_names() {
+        use Weekday::*;
+        assert_eq!(weekdays("0 9 * * MON-FRI"), [Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(weekdays("0 9 * * SUN,3"), [Sun, Wed]);
+        assert_eq!(weekdays("0 9 * * *"), [Sun, Mon, Tue, Wed, Thu, Fri, Sat]);
+    }
+
+    #[test]
+    fn rejects_invalid_weekdays() {
+        for expression in ["0 9 * * 8", "0 9 * * 5-1", "0 9 * * 1/0"] {
+            assert!(parse_cron(expression).is_err(), "{:?} should be rejected", expression);
+        }
+    }
+}
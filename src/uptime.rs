@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use prometheus::GaugeVec;
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, SqlitePool, Row};
+use crate::config::CONFIG;
+use crate::metrics::{self, SERVICE_UPTIME_RATIO, SERVICE_ERROR_BUDGET_REMAINING, SERVICE_ERROR_BUDGET_BURN_RATE, SERVICE_SLO_TARGET};
+use crate::models::Service;
+
+/// Rolling windows reported for every service, as (label, length in seconds).
+pub const WINDOWS: [(&str, i64); 4] = [
+    ("1h", 3600),
+    ("24h", 86_400),
+    ("7d", 7 * 86_400),
+    ("30d", 30 * 86_400),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowUptime {
+    pub window: &'static str,
+    pub total_checks: i64,
+    pub up_checks: i64,
+    /// `None` when no checks ran in the window.
+    pub uptime_percent: Option<f64>,
+    pub error_budget_remaining_percent: Option<f64>,
+    pub burn_rate: Option<f64>,
+}
+
+/// Checks that ran and checks that passed within each of [`WINDOWS`],
+/// leaving out checks made during maintenance windows.
+const WINDOW_COUNTS: &str = "SELECT
+        service_id,
+        COALESCE(SUM(checked_at >= ?1), 0) AS total_0,
+        COALESCE(SUM(checked_at >= ?1 AND status IN ('UP', 'WARNING')), 0) AS up_0,
+        COALESCE(SUM(checked_at >= ?2), 0) AS total_1,
+        COALESCE(SUM(checked_at >= ?2 AND status IN ('UP', 'WARNING')), 0) AS up_1,
+        COALESCE(SUM(checked_at >= ?3), 0) AS total_2,
+        COALESCE(SUM(checked_at >= ?3 AND status IN ('UP', 'WARNING')), 0) AS up_2,
+        COALESCE(SUM(checked_at >= ?4), 0) AS total_3,
+        COALESCE(SUM(checked_at >= ?4 AND status IN ('UP', 'WARNING')), 0) AS up_3
+     FROM check_results
+     WHERE checked_at >= ?4 AND COALESCE(service_state, '') != 'MAINTENANCE'";
+
+fn cutoffs() -> Vec<chrono::DateTime<chrono::Utc>> {
+    let now = chrono::Utc::now();
+    WINDOWS
+        .iter()
+        .map(|(_, secs)| now - chrono::Duration::seconds(*secs))
+        .collect()
+}
+
+fn windows_from_row(row: Option<&SqliteRow>, slo_target: f64) -> Vec<WindowUptime> {
+    WINDOWS
+        .iter()
+        .enumerate()
+        .map(|(i, (window, _))| {
+            let total: i64 = row.map_or(0, |row| row.get(format!("total_{}", i).as_str()));
+            let up: i64 = row.map_or(0, |row| row.get(format!("up_{}", i).as_str()));
+            window_uptime(window, total, up, slo_target)
+        })
+        .collect()
+}
+
+/// Computes availability for each of [`WINDOWS`] from the stored check history.
+pub async fn calculate(pool: &SqlitePool, service: &Service) -> Result<Vec<WindowUptime>, sqlx::Error> {
+    let cutoffs = cutoffs();
+    let row = sqlx::query(&format!("{} AND service_id = ?5", WINDOW_COUNTS))
+        .bind(cutoffs[0])
+        .bind(cutoffs[1])
+        .bind(cutoffs[2])
+        .bind(cutoffs[3])
+        .bind(service.id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(windows_from_row(Some(&row), service.slo_target))
+}
+
+fn window_uptime(window: &'static str, total_checks: i64, up_checks: i64, slo_target: f64) -> WindowUptime {
+    if total_checks == 0 {
+        return WindowUptime {
+            window,
+            total_checks,
+            up_checks,
+            uptime_percent: None,
+            error_budget_remaining_percent: None,
+            burn_rate: None,
+        };
+    }
+
+    let uptime = up_checks as f64 / total_checks as f64;
+    let failure_ratio = 1.0 - uptime;
+    let allowed_failure_ratio = 1.0 - slo_target / 100.0;
+
+    // With a 100% target there is no budget: any failure exhausts it
+    let (remaining, burn_rate) = if allowed_failure_ratio <= 0.0 {
+        if failure_ratio > 0.0 { (0.0, f64::INFINITY) } else { (1.0, 0.0) }
+    } else {
+        let burn = failure_ratio / allowed_failure_ratio;
+        (1.0 - burn, burn)
+    };
+
+    WindowUptime {
+        window,
+        total_checks,
+        up_checks,
+        uptime_percent: Some(uptime * 100.0),
+        error_budget_remaining_percent: Some(remaining * 100.0),
+        burn_rate: if burn_rate.is_finite() { Some(burn_rate) } else { None },
+    }
+}
+
+/// Publishes uptime, SLO target and error budget gauges for a service.
+/// Windows without checks, e.g. during maintenance, drop their series
+/// instead of keeping the last value.
+pub fn export_metrics(service: &Service, windows: &[WindowUptime]) {
+    let [id, url, name] = metrics::service_labels(service);
+    SERVICE_SLO_TARGET.with_label_values(&[&id, &url, &name]).set(service.slo_target / 100.0);
+
+    for w in windows {
+        let labels = [&id[..], &url, &name, w.window];
+        set_or_remove(&SERVICE_UPTIME_RATIO, &labels, w.uptime_percent.map(|uptime| uptime / 100.0));
+        set_or_remove(&SERVICE_ERROR_BUDGET_REMAINING, &labels, w.error_budget_remaining_percent.map(|remaining| remaining / 100.0));
+        set_or_remove(&SERVICE_ERROR_BUDGET_BURN_RATE, &labels, w.burn_rate);
+    }
+}
+
+fn set_or_remove(gauge: &GaugeVec, labels: &[&str], value: Option<f64>) {
+    match value {
+        Some(value) => gauge.with_label_values(labels).set(value),
+        None => {
+            let _ = gauge.remove_label_values(labels);
+        }
+    }
+}
+
+/// When [`refresh_all`] last ran.
+static LAST_REFRESH: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+/// Recomputes and exports uptime metrics for every service in one pass over
+/// the history. Called when `/metrics` is scraped, at most once every
+/// `UPTIME_REFRESH_SECONDS`, as the pass reads up to 30 days of results.
+pub async fn refresh_all(pool: &SqlitePool) {
+    {
+        let mut last_refresh = LAST_REFRESH.lock();
+        if last_refresh.is_some_and(|at| at.elapsed() < Duration::from_secs(CONFIG.uptime_refresh_seconds)) {
+            return;
+        }
+        *last_refresh = Some(Instant::now());
+    }
+
+    let services = match sqlx::query_as::<_, Service>("SELECT * FROM services").fetch_all(pool).await {
+        Ok(services) => services,
+        Err(e) => {
+            tracing::warn!("Failed to load services for uptime: {}", e);
+            return;
+        }
+    };
+
+    let cutoffs = cutoffs();
+    let rows = match sqlx::query(&format!("{} GROUP BY service_id", WINDOW_COUNTS))
+        .bind(cutoffs[0])
+        .bind(cutoffs[1])
+        .bind(cutoffs[2])
+        .bind(cutoffs[3])
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            tracing::warn!("Failed to calculate uptime: {}", e);
+            return;
+        }
+    };
+
+    let rows: HashMap<i64, SqliteRow> = rows.into_iter().map(|row| (row.get("service_id"), row)).collect();
+    for service in &services {
+        export_metrics(service, &windows_from_row(rows.get(&service.id), service.slo_target));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_window_has_no_figures() {
+        let w = window_uptime("1h", 0, 0, 99.9);
+        assert_eq!(w.uptime_percent, None);
+        assert_eq!(w.error_budget_remaining_percent, None);
+        assert_eq!(w.burn_rate, None);
+    }
+
+    #[test]
+    fn budget_is_spent_at_the_target() {
+        let w = window_uptime("30d", 1000, 999, 99.9);
+        assert!((w.uptime_percent.unwrap() - 99.9).abs() < 1e-9);
+        assert!((w.burn_rate.unwrap() - 1.0).abs() < 1e-6);
+        assert!(w.error_budget_remaining_percent.unwrap().abs() < 1e-4);
+    }
+
+    #[test]
+    fn budget_goes_negative_when_exhausted() {
+        let w = window_uptime("24h", 100, 90, 99.0);
+        assert!((w.burn_rate.unwrap() - 10.0).abs() < 1e-9);
+        assert!((w.error_budget_remaining_percent.unwrap() + 900.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn full_target_has_no_budget() {
+        let w = window_uptime("7d", 10, 10, 100.0);
+        assert_eq!(w.error_budget_remaining_percent, Some(100.0));
+        assert_eq!(w.burn_rate, Some(0.0));
+
+        let w = window_uptime("7d", 10, 9, 100.0);
+        assert_eq!(w.error_budget_remaining_percent, Some(0.0));
+        assert_eq!(w.burn_rate, None);
+    }
+}